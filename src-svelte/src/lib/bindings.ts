@@ -11,11 +11,23 @@ declare global {
 const invoke = () => window.__TAURI_INVOKE__;
 
 export function getApiKeys() {
-    return invoke()<ApiKeys>("get_api_keys")
+    return invoke()<MaskedApiKeys>("get_api_keys")
 }
 
-export function setApiKey(filename: string | null, service: Service, apiKey: string) {
-    return invoke()<null>("set_api_key", { filename,service,apiKey })
+export function revealApiKey(service: Service) {
+    return invoke()<string>("reveal_api_key", { service })
+}
+
+export function setApiKey(filename: string | null, service: Service, apiKey: string, validate: boolean | null) {
+    return invoke()<string | null>("set_api_key", { filename,service,apiKey,validate })
+}
+
+export function validateApiKey(service: Service, apiKey: string) {
+    return invoke()<ApiKeyValidation>("validate_api_key", { service,apiKey })
+}
+
+export function unlockApiKeys(passphrase: string) {
+    return invoke()<MaskedApiKeys>("unlock_api_keys", { passphrase })
 }
 
 export function playSound(sound: Sound, volume: number, speed: number) {
@@ -34,23 +46,118 @@ export function getSystemInfo() {
     return invoke()<SystemInfo>("get_system_info")
 }
 
-export function chat(provider: Service, llm: string, temperature: number | null, prompt: ChatMessage[]) {
-    return invoke()<LlmCall>("chat", { provider,llm,temperature,prompt })
+export function chat(provider: Service, llm: string, temperature: number | null, prompt: ChatMessage[], parameters: ChatParameters | null, conversationId: string | null, ignoreBudget: boolean | null, requestId: string | null) {
+    return invoke()<LlmCall>("chat", { provider,llm,temperature,prompt,parameters,conversationId,ignoreBudget,requestId })
+}
+
+export function chatStream(provider: Service, llm: string, temperature: number | null, prompt: ChatMessage[], parameters: ChatParameters | null, conversationId: string | null, ignoreBudget: boolean | null, requestId: string | null) {
+    return invoke()<LlmCall>("chat_stream", { provider,llm,temperature,prompt,parameters,conversationId,ignoreBudget,requestId })
+}
+
+export function cancelChat(requestId: string, recordAborted: boolean | null) {
+    return invoke()<null>("cancel_chat", { requestId,recordAborted })
+}
+
+export function continueGeneration(id: string, ignoreBudget: boolean | null, requestId: string | null) {
+    return invoke()<LlmCall>("continue_generation", { id,ignoreBudget,requestId })
+}
+
+export function createConversation(title: string | null) {
+    return invoke()<Conversation>("create_conversation", { title })
+}
+
+export function listConversations() {
+    return invoke()<Conversation[]>("list_conversations")
+}
+
+export function renameConversation(id: string, title: string) {
+    return invoke()<Conversation>("rename_conversation", { id,title })
+}
+
+export function getConversation(id: string) {
+    return invoke()<ConversationHistory>("get_conversation", { id })
+}
+
+export function deleteConversation(id: string) {
+    return invoke()<null>("delete_conversation", { id })
+}
+
+export function getLlmCalls(filter: LlmCallFilter, before: LlmCallCursor | null, limit: number | null) {
+    return invoke()<LlmCall[]>("get_llm_calls", { filter,before,limit })
+}
+
+export function getLlmCall(id: string) {
+    return invoke()<LlmCall>("get_llm_call", { id })
+}
+
+export function searchLlmCalls(query: string, limit: number | null) {
+    return invoke()<LlmCallSearchResult[]>("search_llm_calls", { query,limit })
+}
+
+export function getUsageSummary(startTime: string | null, endTime: string | null) {
+    return invoke()<UsageSummaryEntry[]>("get_usage_summary", { startTime,endTime })
+}
+
+export function listTools() {
+    return invoke()<ToolInfo[]>("list_tools")
+}
+
+export function getDatabaseStatus() {
+    return invoke()<DatabaseStatus>("get_database_status")
+}
+
+export function reconnectDatabase() {
+    return invoke()<DatabaseStatus>("reconnect_database")
+}
+
+export function switchDatabase(path: string, remember: boolean | null) {
+    return invoke()<DatabaseStatus>("switch_database", { path,remember })
+}
+
+export function backupDatabase(path: string | null) {
+    return invoke()<string>("backup_database", { path })
+}
+
+export function restoreDatabase(path: string) {
+    return invoke()<DatabaseStatus>("restore_database", { path })
 }
 
-export type ApiKeys = { openai: string | null }
 export type SystemInfo = { zamm_version: string; os: OS | null; shell: Shell | null; shell_init_file: string | null }
-export type Request = { prompt: Prompt; temperature: number }
-export type ChatMessage = { role: "System"; text: string } | { role: "Human"; text: string } | { role: "AI"; text: string }
+export type Request = (ChatParameters) & { prompt: Prompt; temperature: number }
+export type ChatMessage = { role: "System"; text: string } | { role: "Human"; text: string; images?: ChatImage[] } | { role: "AI"; text: string } | { role: "AIToolCalls"; tool_calls: ToolCall[] } | { role: "Tool"; tool_call_id: string; text: string }
 export type Llm = { name: string; requested: string; provider: Service }
-export type Response = { completion: ChatMessage }
-export type Preferences = { animations_on: boolean | null; background_animation: boolean | null; animation_speed: number | null; sound_on: boolean | null; volume: number | null }
-export type Service = "OpenAI"
+export type Response = { completion: ChatMessage; finish_reason: FinishReason | null; choices?: CompletionChoices }
+export type Preferences = { animations_on: boolean | null; background_animation: boolean | null; animation_speed: number | null; sound_on: boolean | null; volume: number | null; ollama_base_url: string | null; pricing: ModelPricing[] | null; budgets: Budget[] | null; max_request_attempts: number | null; request_timeout_seconds: number | null; database_path: string | null }
+export type Service = "OpenAI" | "Anthropic" | "Ollama"
 export type EntityId = { id: string }
 export type OS = "Mac" | "Linux" | "Windows"
 export type Shell = "Bash" | "Zsh"
-export type LlmCall = ({ id: string }) & { timestamp: string; llm: Llm; request: Request; response: Response; tokens: TokenMetadata }
+export type LlmCall = ({ id: string }) & { timestamp: string; llm: Llm; request: Request; response: Response; tokens: TokenMetadata; cost: number | null; conversation_id: EntityId | null; previous_call_id: EntityId | null; warning?: string | null }
 export type TokenMetadata = { prompt: number | null; response: number | null; total: number | null }
+export type Conversation = ({ id: string }) & { title: string; created_at: string; updated_at: string }
+export type ConversationHistory = { conversation: Conversation; llm_calls: LlmCall[]; messages: ChatMessage[] }
+export type LlmCallFilter = { provider: Service | null; llm: string | null; start_time: string | null; end_time: string | null; min_tokens: number | null; max_tokens: number | null }
+export type LlmCallCursor = ({ id: string }) & { timestamp: string }
+export type LlmCallSearchResult = { llm_call: LlmCall; snippet: string }
+export type ModelPricing = { provider: Service; model: string; prompt_per_million: number; response_per_million: number }
+export type UsageSummaryEntry = { date: string; provider: Service; llm: string; num_calls: number; prompt_tokens: number; response_tokens: number; total_tokens: number; cost: number }
+export type BudgetPeriod = "Daily" | "Monthly"
+export type Budget = { provider: Service; period: BudgetPeriod; max_cost: number | null; max_tokens: number | null }
+export type ChatParameters = { max_tokens: number | null; top_p: number | null; presence_penalty: number | null; frequency_penalty: number | null; stop: StopSequences | null; seed: number | null; response_format: ResponseFormat | null; n: number | null; tools: ToolNames | null }
+export type StopSequences = string[]
+export type ResponseFormat = "text" | "json_object"
+export type FinishReason = "stop" | "length" | "tool_calls" | "content_filter" | "function_call" | "aborted"
+export type CompletionChoice = { completion: ChatMessage; finish_reason: FinishReason | null }
+export type CompletionChoices = CompletionChoice[]
+export type ChatImage = { source: "path"; path: string } | { source: "base64"; media_type: string; data: string } | { source: "blob"; media_type: string; hash: string }
+export type ToolCall = { id: string; name: string; arguments: string }
+export type ToolNames = string[]
+export type ToolInfo = { name: string; description: string }
+export type DatabaseStatus = { path: string | null; available: boolean; migration_version: string | null; last_error: string | null }
+export type MaskedApiKeys = { openai: MaskedApiKey | null; anthropic: MaskedApiKey | null; locked: boolean; plaintext_in_backups: boolean }
+export type MaskedApiKey = { prefix: string; last_four: string; source: ApiKeySource | null }
+export type ApiKeySource = { type: "Environment"; env_var: string } | { type: "SecretStore" } | { type: "Database" } | { type: "InitFile"; path: string }
+export type ApiKeyValidation = { status: "valid" } | { status: "invalid"; reason: string } | { status: "network_error"; reason: string }
 export type Sound = "Switch" | "Whoosh"
 export type Prompt = ({ type: "Chat" } & ChatPrompt)
 export type ChatPrompt = { messages: ChatMessage[] }
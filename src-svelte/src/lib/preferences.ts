@@ -19,4 +19,10 @@ export const NullPreferences: Preferences = {
   animation_speed: null,
   sound_on: null,
   volume: null,
+  ollama_base_url: null,
+  pricing: null,
+  budgets: null,
+  max_request_attempts: null,
+  request_timeout_seconds: null,
+  database_path: null,
 };
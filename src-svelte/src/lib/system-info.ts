@@ -1,9 +1,12 @@
 import { writable, type Writable } from "svelte/store";
-import type { SystemInfo, ApiKeys } from "./bindings";
+import type { SystemInfo, MaskedApiKeys } from "./bindings";
 
 export const systemInfo: Writable<SystemInfo | undefined> = writable(undefined);
-export const apiKeys: Writable<ApiKeys> = writable({
+export const apiKeys: Writable<MaskedApiKeys> = writable({
   openai: null,
+  anthropic: null,
+  locked: false,
+  plaintext_in_backups: false,
 });
 
 export const NullSystemInfo: SystemInfo = {
@@ -17,6 +17,9 @@ describe("Chat conversation", () => {
   });
 
   beforeEach(() => {
+    vi.spyOn(crypto, "randomUUID").mockReturnValue(
+      "9aee91a3-4a0c-4089-8b17-7f9b8116f4ac",
+    );
     tauriInvokeMock = vi.fn();
     vi.stubGlobal("__TAURI_INVOKE__", tauriInvokeMock);
     playback = new TauriInvokePlayback();
@@ -68,7 +71,7 @@ describe("Chat conversation", () => {
     >;
     const nextExpectedMessage = nextExpectedCallArgs["prompt"].slice(
       -1,
-    )[0] as ChatMessage;
+    )[0] as Extract<ChatMessage, { role: "Human" }>;
     const nextExpectedHumanPrompt = nextExpectedMessage.text;
 
     const chatInput = screen.getByLabelText("Chat with the AI:");
@@ -120,7 +123,7 @@ describe("Chat conversation", () => {
     >;
     const nextExpectedMessage = nextExpectedCallArgs["prompt"].slice(
       -1,
-    )[0] as ChatMessage;
+    )[0] as Extract<ChatMessage, { role: "Human" }>;
     const nextExpectedHumanPrompt = nextExpectedMessage.text;
 
     const chatInput = screen.getByLabelText("Chat with the AI:");
@@ -136,4 +139,34 @@ describe("Chat conversation", () => {
     expect(tauriInvokeMock).toHaveBeenCalledTimes(1);
     expect(screen.getByText(nextExpectedHumanPrompt)).toBeInTheDocument();
   });
+
+  test("can stop a response that is still coming in", async () => {
+    render(Chat, {});
+    playback.callPauseMs = 1_000;
+    playback.addSamples(
+      "../src-tauri/api/sample-calls/chat-start-conversation.yaml",
+    );
+    playback.addCalls({
+      request: [
+        "cancel_chat",
+        {
+          requestId: "9aee91a3-4a0c-4089-8b17-7f9b8116f4ac",
+          recordAborted: null,
+        } as unknown as Record<string, string>,
+      ],
+      response: {},
+      succeeded: true,
+    });
+    expect(screen.queryByRole("button", { name: "Stop" })).toBeNull();
+
+    const chatInput = screen.getByLabelText("Chat with the AI:");
+    await userEvent.type(chatInput, "Hello, does this work?");
+    await userEvent.click(screen.getByRole("button", { name: "Send" }));
+    await userEvent.click(screen.getByRole("button", { name: "Stop" }));
+    expect(tauriInvokeMock).toHaveBeenCalledTimes(2);
+    expect(tauriInvokeMock).toHaveBeenLastCalledWith("cancel_chat", {
+      requestId: "9aee91a3-4a0c-4089-8b17-7f9b8116f4ac",
+      recordAborted: null,
+    });
+  });
 });
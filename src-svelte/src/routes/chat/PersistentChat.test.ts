@@ -17,6 +17,9 @@ describe("Chat conversation", () => {
   });
 
   beforeEach(() => {
+    vi.spyOn(crypto, "randomUUID").mockReturnValue(
+      "9aee91a3-4a0c-4089-8b17-7f9b8116f4ac",
+    );
     tauriInvokeMock = vi.fn();
     vi.stubGlobal("__TAURI_INVOKE__", tauriInvokeMock);
     playback = new TauriInvokePlayback();
@@ -55,7 +58,7 @@ describe("Chat conversation", () => {
     >;
     const nextExpectedMessage = nextExpectedCallArgs["prompt"].slice(
       -1,
-    )[0] as ChatMessage;
+    )[0] as Extract<ChatMessage, { role: "Human" }>;
     const nextExpectedHumanPrompt = nextExpectedMessage.text;
 
     const chatInput = screen.getByLabelText("Chat with the AI:");
@@ -28,6 +28,7 @@ const Template = ({ ...args }) => ({
 const writeToFile = "/api/sample-calls/set_api_key-existing-no-newline.yaml";
 const unknownKeys = "/api/sample-calls/get_api_keys-empty.yaml";
 const knownKeys = "/api/sample-calls/get_api_keys-openai.yaml";
+const revealKey = "/api/sample-calls/reveal_api_key-openai.yaml";
 const unsetKey = "/api/sample-calls/set_api_key-unset.yaml";
 
 export const Loading: StoryObj = Template.bind({}) as any;
@@ -48,7 +49,7 @@ Unknown.parameters = {
 
 export const Known: StoryObj = Template.bind({}) as any;
 Known.parameters = {
-  sampleCallFiles: [knownKeys, writeToFile],
+  sampleCallFiles: [knownKeys, revealKey, writeToFile],
   viewport: {
     defaultViewport: "mobile2",
   },
@@ -70,7 +71,7 @@ EditingPreFilled.args = {
   editDemo: true,
 };
 EditingPreFilled.parameters = {
-  sampleCallFiles: [knownKeys, writeToFile],
+  sampleCallFiles: [knownKeys, revealKey, writeToFile],
   stores: {
     systemInfo: {
       shell_init_file: "/root/.profile",
@@ -87,15 +87,49 @@ describe("API Keys Display", () => {
       "../src-tauri/api/sample-calls/get_api_keys-openai.yaml",
       "Active",
     );
+    playback.addSamples(
+      "../src-tauri/api/sample-calls/reveal_api_key-openai.yaml",
+    );
 
     await toggleOpenAIForm();
-    const apiKeyInput = screen.getByLabelText("API key:");
-    expect(apiKeyInput).toHaveValue("0p3n41-4p1-k3y");
+    // the key stays hidden until the user asks to see it
+    let apiKeyInput = screen.getByLabelText("API key:");
+    expect(apiKeyInput).toHaveValue("");
+    expect(apiKeyInput).toHaveAttribute("type", "password");
+    expect(apiKeyInput).toHaveAttribute("placeholder", "0p3...-k3y");
+    expect(tauriInvokeMock).toHaveReturnedTimes(1);
+
+    await userEvent.click(screen.getByRole("button", { name: "Reveal" }));
+    await waitFor(() => {
+      apiKeyInput = screen.getByLabelText("API key:");
+      expect(apiKeyInput).toHaveValue("0p3n41-4p1-k3y");
+    });
+    expect(apiKeyInput).toHaveAttribute("type", "text");
     const saveFileInput = screen.getByLabelText("Export from:");
     expect(saveFileInput).toHaveValue("/home/rando/.zshrc");
   });
 
-  test("API key error", async () => {
+  test("warns about plaintext keys in old backups", async () => {
+    playback.addCalls({
+      request: ["get_api_keys"],
+      response: {
+        openai: null,
+        anthropic: null,
+        locked: false,
+        plaintext_in_backups: true,
+      } as unknown as Record<string, string>,
+      succeeded: true,
+    });
+
+    render(ApiKeysDisplay, {});
+    await waitFor(() =>
+      expect(screen.getByRole("note")).toHaveTextContent(
+        /backups made before now still contain them in plaintext/,
+      ),
+    );
+  });
+
+    test("API key error", async () => {
     const errorMessage = "Testing error message";
     const spy = vi.spyOn(window, "__TAURI_INVOKE__");
     expect(spy).not.toHaveBeenCalled();
@@ -170,6 +204,35 @@ describe("API Keys Display", () => {
     await waitFor(() => expect(apiKeyInput).not.toBeInTheDocument());
   });
 
+  test("keeps existing API key when saving without revealing it", async () => {
+    systemInfo.set({
+      ...NullSystemInfo,
+      shell_init_file: "no-newline/.bashrc",
+    });
+    await checkSampleCall(
+      "../src-tauri/api/sample-calls/get_api_keys-openai.yaml",
+      "Active",
+    );
+    tauriInvokeMock.mockClear();
+    playback.addSamples(
+      "../src-tauri/api/sample-calls/reveal_api_key-openai.yaml",
+      "../src-tauri/api/sample-calls/set_api_key-existing-no-newline.yaml",
+      "../src-tauri/api/sample-calls/get_api_keys-openai.yaml",
+    );
+
+    await toggleOpenAIForm();
+    const apiKeyInput = screen.getByLabelText("API key:");
+    await userEvent.click(screen.getByRole("button", { name: "Save" }));
+    await waitFor(() => expect(tauriInvokeMock).toHaveReturnedTimes(3));
+    expect(tauriInvokeMock).toHaveBeenNthCalledWith(2, "set_api_key", {
+      filename: "no-newline/.bashrc",
+      service: "OpenAI",
+      apiKey: "0p3n41-4p1-k3y",
+      validate: true,
+    });
+    await waitFor(() => expect(apiKeyInput).not.toBeInTheDocument());
+  });
+
   test("preserves unsubmitted changes after opening and closing form", async () => {
     const defaultInitFile = "/home/rando/.bashrc";
     systemInfo.set({
@@ -287,6 +350,87 @@ describe("API Keys Display", () => {
     await waitFor(() => expect(tauriInvokeMock).toHaveReturnedTimes(2));
   });
 
+  test("can save a key without checking it first", async () => {
+    systemInfo.set({
+      ...NullSystemInfo,
+    });
+    await checkSampleCall(
+      "../src-tauri/api/sample-calls/get_api_keys-empty.yaml",
+      "Inactive",
+    );
+    tauriInvokeMock.mockClear();
+    playback.addCalls({
+      request: [
+        "set_api_key",
+        {
+          filename: null,
+          service: "OpenAI",
+          apiKey: "0p3n41-4p1-k3y",
+          validate: false,
+        } as unknown as Record<string, string>,
+      ],
+      response: null as unknown as Record<string, string>,
+      succeeded: true,
+    });
+    playback.addSamples(
+      "../src-tauri/api/sample-calls/get_api_keys-openai.yaml",
+    );
+
+    await toggleOpenAIForm();
+    const validateCheckbox = screen.getByLabelText("Check key before saving");
+    expect(validateCheckbox).toBeChecked();
+    await userEvent.click(validateCheckbox);
+    await userEvent.click(
+      screen.getByLabelText("Export as environment variable?"),
+    );
+    await userEvent.type(screen.getByLabelText("API key:"), "0p3n41-4p1-k3y");
+    await userEvent.click(screen.getByRole("button", { name: "Save" }));
+    await waitFor(() => expect(tauriInvokeMock).toHaveReturnedTimes(2));
+  });
+
+  test("shows warning from checking key", async () => {
+    const warning =
+      "The key could not be checked: error sending request for url " +
+      "(https://api.openai.com/v1/models)";
+    systemInfo.set({
+      ...NullSystemInfo,
+    });
+    await checkSampleCall(
+      "../src-tauri/api/sample-calls/get_api_keys-empty.yaml",
+      "Inactive",
+    );
+    tauriInvokeMock.mockClear();
+    playback.addCalls({
+      request: [
+        "set_api_key",
+        {
+          filename: null,
+          service: "OpenAI",
+          apiKey: "0p3n41-4p1-k3y",
+          validate: true,
+        } as unknown as Record<string, string>,
+      ],
+      response: warning as unknown as Record<string, string>,
+      succeeded: true,
+    });
+    playback.addSamples(
+      "../src-tauri/api/sample-calls/get_api_keys-openai.yaml",
+    );
+
+    await toggleOpenAIForm();
+    await userEvent.click(
+      screen.getByLabelText("Export as environment variable?"),
+    );
+    await userEvent.type(screen.getByLabelText("API key:"), "0p3n41-4p1-k3y");
+    await userEvent.click(screen.getByRole("button", { name: "Save" }));
+    await waitFor(() => expect(tauriInvokeMock).toHaveReturnedTimes(2));
+
+    render(Snackbar, {});
+    const alerts = screen.queryAllByRole("alertdialog");
+    expect(alerts).toHaveLength(1);
+    expect(alerts[0]).toHaveTextContent(warning);
+  });
+
   test("can submit with invalid file", async () => {
     systemInfo.set({
       ...NullSystemInfo,
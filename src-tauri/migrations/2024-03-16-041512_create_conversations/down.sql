@@ -0,0 +1,4 @@
+DROP INDEX llm_calls_conversation_id;
+ALTER TABLE llm_calls DROP COLUMN previous_call_id;
+ALTER TABLE llm_calls DROP COLUMN conversation_id;
+DROP TABLE conversations;
@@ -0,0 +1,10 @@
+CREATE TABLE conversations (
+  id VARCHAR PRIMARY KEY NOT NULL,
+  title VARCHAR NOT NULL,
+  created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+ALTER TABLE llm_calls ADD COLUMN conversation_id VARCHAR;
+ALTER TABLE llm_calls ADD COLUMN previous_call_id VARCHAR;
+CREATE INDEX llm_calls_conversation_id ON llm_calls (conversation_id);
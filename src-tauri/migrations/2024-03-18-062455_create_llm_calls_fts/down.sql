@@ -0,0 +1,4 @@
+DROP TRIGGER llm_calls_fts_delete;
+DROP TRIGGER llm_calls_fts_update;
+DROP TRIGGER llm_calls_fts_insert;
+DROP TABLE llm_calls_fts;
@@ -0,0 +1,37 @@
+-- Prompts and completions are stored as JSON, so index only the message text
+CREATE VIRTUAL TABLE llm_calls_fts USING fts5(
+  id UNINDEXED,
+  prompt,
+  completion
+);
+
+INSERT INTO llm_calls_fts (id, prompt, completion)
+SELECT
+  id,
+  (SELECT group_concat(json_extract(value, '$.text'), char(10))
+    FROM json_each(llm_calls.prompt, '$.messages')),
+  json_extract(completion, '$.text')
+FROM llm_calls;
+
+CREATE TRIGGER llm_calls_fts_insert AFTER INSERT ON llm_calls BEGIN
+  INSERT INTO llm_calls_fts (id, prompt, completion)
+  VALUES (
+    new.id,
+    (SELECT group_concat(json_extract(value, '$.text'), char(10))
+      FROM json_each(new.prompt, '$.messages')),
+    json_extract(new.completion, '$.text')
+  );
+END;
+
+CREATE TRIGGER llm_calls_fts_update AFTER UPDATE OF prompt, completion ON llm_calls
+BEGIN
+  UPDATE llm_calls_fts SET
+    prompt = (SELECT group_concat(json_extract(value, '$.text'), char(10))
+      FROM json_each(new.prompt, '$.messages')),
+    completion = json_extract(new.completion, '$.text')
+  WHERE id = old.id;
+END;
+
+CREATE TRIGGER llm_calls_fts_delete AFTER DELETE ON llm_calls BEGIN
+  DELETE FROM llm_calls_fts WHERE id = old.id;
+END;
@@ -0,0 +1 @@
+ALTER TABLE llm_calls ADD COLUMN cost REAL;
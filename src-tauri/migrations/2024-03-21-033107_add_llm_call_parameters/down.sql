@@ -0,0 +1,7 @@
+ALTER TABLE llm_calls DROP COLUMN response_format;
+ALTER TABLE llm_calls DROP COLUMN seed;
+ALTER TABLE llm_calls DROP COLUMN stop;
+ALTER TABLE llm_calls DROP COLUMN frequency_penalty;
+ALTER TABLE llm_calls DROP COLUMN presence_penalty;
+ALTER TABLE llm_calls DROP COLUMN top_p;
+ALTER TABLE llm_calls DROP COLUMN max_tokens;
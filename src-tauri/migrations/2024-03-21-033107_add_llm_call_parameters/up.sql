@@ -0,0 +1,7 @@
+ALTER TABLE llm_calls ADD COLUMN max_tokens INTEGER;
+ALTER TABLE llm_calls ADD COLUMN top_p REAL;
+ALTER TABLE llm_calls ADD COLUMN presence_penalty REAL;
+ALTER TABLE llm_calls ADD COLUMN frequency_penalty REAL;
+ALTER TABLE llm_calls ADD COLUMN stop TEXT;
+ALTER TABLE llm_calls ADD COLUMN seed BIGINT;
+ALTER TABLE llm_calls ADD COLUMN response_format VARCHAR;
@@ -0,0 +1,2 @@
+ALTER TABLE llm_calls DROP COLUMN choices;
+ALTER TABLE llm_calls DROP COLUMN n;
@@ -0,0 +1,2 @@
+ALTER TABLE llm_calls ADD COLUMN n INTEGER;
+ALTER TABLE llm_calls ADD COLUMN choices TEXT;
@@ -0,0 +1 @@
+ALTER TABLE llm_calls ADD COLUMN finish_reason VARCHAR;
@@ -0,0 +1 @@
+ALTER TABLE llm_calls ADD COLUMN tools TEXT;
@@ -0,0 +1,30 @@
+DROP TRIGGER llm_calls_fts_insert;
+DROP TRIGGER llm_calls_fts_update;
+
+DELETE FROM llm_calls_fts;
+INSERT INTO llm_calls_fts (id, prompt, completion)
+SELECT
+  id,
+  (SELECT group_concat(json_extract(value, '$.text'), char(10))
+    FROM json_each(llm_calls.prompt, '$.messages')),
+  json_extract(completion, '$.text')
+FROM llm_calls;
+
+CREATE TRIGGER llm_calls_fts_insert AFTER INSERT ON llm_calls BEGIN
+  INSERT INTO llm_calls_fts (id, prompt, completion)
+  VALUES (
+    new.id,
+    (SELECT group_concat(json_extract(value, '$.text'), char(10))
+      FROM json_each(new.prompt, '$.messages')),
+    json_extract(new.completion, '$.text')
+  );
+END;
+
+CREATE TRIGGER llm_calls_fts_update AFTER UPDATE OF prompt, completion ON llm_calls
+BEGIN
+  UPDATE llm_calls_fts SET
+    prompt = (SELECT group_concat(json_extract(value, '$.text'), char(10))
+      FROM json_each(new.prompt, '$.messages')),
+    completion = json_extract(new.completion, '$.text')
+  WHERE id = old.id;
+END;
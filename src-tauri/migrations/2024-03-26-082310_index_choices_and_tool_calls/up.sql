@@ -0,0 +1,102 @@
+-- Alternate choices and tool calls have no top-level text, so they get indexed
+-- separately from it
+DROP TRIGGER llm_calls_fts_insert;
+DROP TRIGGER llm_calls_fts_update;
+
+DELETE FROM llm_calls_fts;
+INSERT INTO llm_calls_fts (id, prompt, completion)
+SELECT
+  id,
+  (SELECT group_concat(
+        coalesce(
+          json_extract(message.value, '$.text'),
+          (SELECT group_concat(
+              json_extract(tool_call.value, '$.name') || ' ' ||
+                json_extract(tool_call.value, '$.arguments'),
+              char(10))
+            FROM json_each(message.value, '$.tool_calls') AS tool_call)
+        ),
+        char(10))
+      FROM json_each(llm_calls.prompt, '$.messages') AS message),
+  (SELECT group_concat(text, char(10)) FROM (
+        SELECT coalesce(
+          json_extract(llm_calls.completion, '$.text'),
+          (SELECT group_concat(
+              json_extract(tool_call.value, '$.name') || ' ' ||
+                json_extract(tool_call.value, '$.arguments'),
+              char(10))
+            FROM json_each(llm_calls.completion, '$.tool_calls') AS tool_call)
+        ) AS text
+        -- the first choice is the completion itself
+        UNION ALL
+        SELECT json_extract(choice.value, '$.completion.text') AS text
+          FROM json_each(llm_calls.choices) AS choice
+          WHERE choice.key > 0
+      ))
+FROM llm_calls;
+
+CREATE TRIGGER llm_calls_fts_insert AFTER INSERT ON llm_calls BEGIN
+  INSERT INTO llm_calls_fts (id, prompt, completion)
+  VALUES (
+    new.id,
+    (SELECT group_concat(
+          coalesce(
+            json_extract(message.value, '$.text'),
+            (SELECT group_concat(
+                json_extract(tool_call.value, '$.name') || ' ' ||
+                  json_extract(tool_call.value, '$.arguments'),
+                char(10))
+              FROM json_each(message.value, '$.tool_calls') AS tool_call)
+          ),
+          char(10))
+        FROM json_each(new.prompt, '$.messages') AS message),
+    (SELECT group_concat(text, char(10)) FROM (
+          SELECT coalesce(
+            json_extract(new.completion, '$.text'),
+            (SELECT group_concat(
+                json_extract(tool_call.value, '$.name') || ' ' ||
+                  json_extract(tool_call.value, '$.arguments'),
+                char(10))
+              FROM json_each(new.completion, '$.tool_calls') AS tool_call)
+          ) AS text
+          -- the first choice is the completion itself
+          UNION ALL
+          SELECT json_extract(choice.value, '$.completion.text') AS text
+            FROM json_each(new.choices) AS choice
+            WHERE choice.key > 0
+        ))
+  );
+END;
+
+CREATE TRIGGER llm_calls_fts_update
+AFTER UPDATE OF prompt, completion, choices ON llm_calls
+BEGIN
+  UPDATE llm_calls_fts SET
+    prompt = (SELECT group_concat(
+          coalesce(
+            json_extract(message.value, '$.text'),
+            (SELECT group_concat(
+                json_extract(tool_call.value, '$.name') || ' ' ||
+                  json_extract(tool_call.value, '$.arguments'),
+                char(10))
+              FROM json_each(message.value, '$.tool_calls') AS tool_call)
+          ),
+          char(10))
+        FROM json_each(new.prompt, '$.messages') AS message),
+    completion = (SELECT group_concat(text, char(10)) FROM (
+          SELECT coalesce(
+            json_extract(new.completion, '$.text'),
+            (SELECT group_concat(
+                json_extract(tool_call.value, '$.name') || ' ' ||
+                  json_extract(tool_call.value, '$.arguments'),
+                char(10))
+              FROM json_each(new.completion, '$.tool_calls') AS tool_call)
+          ) AS text
+          -- the first choice is the completion itself
+          UNION ALL
+          SELECT json_extract(choice.value, '$.completion.text') AS text
+            FROM json_each(new.choices) AS choice
+            WHERE choice.key > 0
+        ))
+  WHERE id = old.id;
+END;
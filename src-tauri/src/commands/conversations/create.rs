@@ -0,0 +1,62 @@
+use crate::commands::errors::ZammResult;
+use crate::models::conversations::Conversation;
+use crate::models::llm_calls::EntityId;
+use crate::schema::conversations;
+use crate::ZammDatabase;
+use diesel::RunQueryDsl;
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+const DEFAULT_TITLE: &str = "New conversation";
+
+async fn create_conversation_helper(
+    zamm_db: &ZammDatabase,
+    title: Option<String>,
+) -> ZammResult<Conversation> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let conversation = Conversation {
+        id: EntityId {
+            uuid: Uuid::new_v4(),
+        },
+        title: title.unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+        created_at: now,
+        updated_at: now,
+    };
+    diesel::insert_into(conversations::table)
+        .values(conversation.as_sql_row())
+        .execute(conn)?;
+    Ok(conversation)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn create_conversation(
+    database: State<'_, ZammDatabase>,
+    title: Option<String>,
+) -> ZammResult<Conversation> {
+    create_conversation_helper(&database, title).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::setup_zamm_db;
+
+    #[tokio::test]
+    async fn test_create_default_title() {
+        let db = setup_zamm_db();
+        let conversation = create_conversation_helper(&db, None).await.unwrap();
+        assert_eq!(conversation.title, DEFAULT_TITLE);
+        assert_eq!(conversation.created_at, conversation.updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_create_without_db() {
+        let db = ZammDatabase::new(None, None);
+        let result = create_conversation_helper(&db, Some("Test".to_string())).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use crate::commands::errors::ZammResult;
+use crate::models::llm_calls::EntityId;
+use crate::schema::{conversations, llm_calls};
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+/// Deletes a conversation. The LLM calls that were part of it are kept, but are no
+/// longer associated with any conversation.
+async fn delete_conversation_helper(
+    zamm_db: &ZammDatabase,
+    id: Uuid,
+) -> ZammResult<()> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let conversation_id = EntityId { uuid: id };
+    conn.transaction(|conn| {
+        diesel::update(
+            llm_calls::table.filter(llm_calls::conversation_id.eq(&conversation_id)),
+        )
+        .set(llm_calls::conversation_id.eq(None::<EntityId>))
+        .execute(conn)?;
+        let num_deleted = diesel::delete(conversations::table.find(&conversation_id))
+            .execute(conn)?;
+        if num_deleted == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn delete_conversation(
+    database: State<'_, ZammDatabase>,
+    id: Uuid,
+) -> ZammResult<()> {
+    delete_conversation_helper(&database, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::test_helpers::setup_zamm_db;
+
+    #[tokio::test]
+    async fn test_delete() {
+        let db = setup_zamm_db();
+        let conversation =
+            insert_conversation(&db, "To delete", "2024-03-01T10:00:00").await;
+
+        delete_conversation_helper(&db, conversation.id.uuid)
+            .await
+            .unwrap();
+        let mut conn = db.connection().unwrap();
+        let remaining: i64 =
+            conversations::table.count().get_result(&mut *conn).unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_nonexistent() {
+        let db = setup_zamm_db();
+        assert!(delete_conversation_helper(&db, Uuid::new_v4())
+            .await
+            .is_err());
+    }
+}
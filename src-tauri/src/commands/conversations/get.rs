@@ -0,0 +1,186 @@
+use crate::commands::errors::ZammResult;
+use crate::models::conversations::Conversation;
+use crate::models::llm_calls::{
+    ChatMessage, ChatPrompt, EntityId, FinishReason, LlmCall, LlmCallRow, Prompt,
+};
+use crate::schema::{conversations, llm_calls};
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct ConversationHistory {
+    pub conversation: Conversation,
+    pub llm_calls: Vec<LlmCall>,
+    /// The full chat so far, as seen by the most recent LLM call.
+    pub messages: Vec<ChatMessage>,
+}
+
+async fn get_conversation_helper(
+    zamm_db: &ZammDatabase,
+    id: Uuid,
+) -> ZammResult<ConversationHistory> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let conversation_id = EntityId { uuid: id };
+    let conversation = conversations::table
+        .find(&conversation_id)
+        .select(Conversation::as_select())
+        .first(conn)?;
+    let llm_calls: Vec<LlmCall> = llm_calls::table
+        .filter(llm_calls::conversation_id.eq(&conversation_id))
+        .order(llm_calls::timestamp.asc())
+        .load::<LlmCallRow>(conn)?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+
+    // aborted calls are kept in the history, but have nothing to add to the chat
+    let last_completed_call = llm_calls
+        .iter()
+        .rev()
+        .find(|call| call.response.finish_reason != Some(FinishReason::Aborted));
+    let messages = match last_completed_call {
+        Some(last_call) => {
+            let Prompt::Chat(ChatPrompt { messages }) = &last_call.request.prompt;
+            let mut messages = messages.clone();
+            messages.push(last_call.response.completion.clone());
+            messages
+        }
+        None => vec![],
+    };
+
+    Ok(ConversationHistory {
+        conversation,
+        llm_calls,
+        messages,
+    })
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn get_conversation(
+    database: State<'_, ZammDatabase>,
+    id: Uuid,
+) -> ZammResult<ConversationHistory> {
+    get_conversation_helper(&database, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::conversations::get_latest_call_id;
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::models::llm_calls::{CompletionChoices, Response};
+    use crate::test_helpers::setup_zamm_db;
+
+    async fn insert_sample_call(
+        zamm_db: &ZammDatabase,
+        sample_path: &str,
+        timestamp: &str,
+        conversation_id: &EntityId,
+        previous_call_id: Option<&EntityId>,
+    ) -> LlmCall {
+        let llm_call = LlmCall {
+            conversation_id: Some(conversation_id.clone()),
+            previous_call_id: previous_call_id.cloned(),
+            ..read_sample_llm_call(sample_path, timestamp)
+        };
+        insert_llm_call(zamm_db, &llm_call).await;
+        llm_call
+    }
+
+    #[tokio::test]
+    async fn test_get_conversation_history() {
+        let db = setup_zamm_db();
+        let conversation =
+            insert_conversation(&db, "Greetings", "2024-03-01T10:00:00").await;
+        let first = insert_sample_call(
+            &db,
+            "api/sample-calls/chat-start-conversation.yaml",
+            "2024-03-01T10:01:00",
+            &conversation.id,
+            None,
+        )
+        .await;
+        let second = insert_sample_call(
+            &db,
+            "api/sample-calls/chat-continue-conversation.yaml",
+            "2024-03-01T10:02:00",
+            &conversation.id,
+            Some(&first.id),
+        )
+        .await;
+
+        let history = get_conversation_helper(&db, conversation.id.uuid)
+            .await
+            .unwrap();
+        assert_eq!(history.conversation.title, "Greetings");
+        let call_ids: Vec<&EntityId> =
+            history.llm_calls.iter().map(|call| &call.id).collect();
+        assert_eq!(call_ids, vec![&first.id, &second.id]);
+        assert_eq!(history.messages.last(), Some(&second.response.completion));
+    }
+
+    #[tokio::test]
+    async fn test_aborted_call_left_out_of_chat() {
+        let db = setup_zamm_db();
+        let conversation =
+            insert_conversation(&db, "Greetings", "2024-03-01T10:00:00").await;
+        let first = insert_sample_call(
+            &db,
+            "api/sample-calls/chat-start-conversation.yaml",
+            "2024-03-01T10:01:00",
+            &conversation.id,
+            None,
+        )
+        .await;
+        // as recorded when a follow-up is cancelled before the reply comes in
+        let aborted = LlmCall {
+            id: EntityId {
+                uuid: Uuid::new_v4(),
+            },
+            response: Response {
+                completion: ChatMessage::AI {
+                    text: String::new(),
+                },
+                finish_reason: Some(FinishReason::Aborted),
+                choices: CompletionChoices::default(),
+            },
+            conversation_id: Some(conversation.id.clone()),
+            previous_call_id: Some(first.id.clone()),
+            ..read_sample_llm_call(
+                "api/sample-calls/chat-continue-conversation.yaml",
+                "2024-03-01T10:02:00",
+            )
+        };
+        insert_llm_call(&db, &aborted).await;
+
+        let history = get_conversation_helper(&db, conversation.id.uuid)
+            .await
+            .unwrap();
+        assert_eq!(history.llm_calls.len(), 2);
+        assert_eq!(history.messages.last(), Some(&first.response.completion));
+
+        let latest_call_id =
+            get_latest_call_id(&mut *db.connection().unwrap(), &conversation.id);
+        assert_eq!(latest_call_id.unwrap(), Some(first.id));
+    }
+
+    #[tokio::test]
+    async fn test_get_empty_conversation() {
+        let db = setup_zamm_db();
+        let conversation =
+            insert_conversation(&db, "Empty", "2024-03-01T10:00:00").await;
+
+        let history = get_conversation_helper(&db, conversation.id.uuid)
+            .await
+            .unwrap();
+        assert!(history.llm_calls.is_empty());
+        assert!(history.messages.is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+use crate::commands::errors::ZammResult;
+use crate::models::conversations::Conversation;
+use crate::schema::conversations;
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use specta::specta;
+use tauri::State;
+
+async fn list_conversations_helper(
+    zamm_db: &ZammDatabase,
+) -> ZammResult<Vec<Conversation>> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let results = conversations::table
+        .order(conversations::updated_at.desc())
+        .select(Conversation::as_select())
+        .load(conn)?;
+    Ok(results)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn list_conversations(
+    database: State<'_, ZammDatabase>,
+) -> ZammResult<Vec<Conversation>> {
+    list_conversations_helper(&database).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::test_helpers::setup_zamm_db;
+
+    #[tokio::test]
+    async fn test_list_most_recently_updated_first() {
+        let db = setup_zamm_db();
+        insert_conversation(&db, "Older", "2024-03-01T10:00:00").await;
+        insert_conversation(&db, "Newer", "2024-03-02T10:00:00").await;
+
+        let conversations = list_conversations_helper(&db).await.unwrap();
+        let titles: Vec<&str> =
+            conversations.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Newer", "Older"]);
+    }
+}
@@ -0,0 +1,84 @@
+mod create;
+mod delete;
+mod get;
+mod list;
+mod rename;
+
+use crate::commands::errors::ZammResult;
+use crate::models::llm_calls::{EntityId, FinishReason};
+use crate::schema::{conversations, llm_calls};
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+
+pub use create::create_conversation;
+pub use delete::delete_conversation;
+pub use get::get_conversation;
+pub use list::list_conversations;
+pub use rename::rename_conversation;
+
+/// Returns the ID of the most recent LLM call in a conversation, so that the next
+/// call can be linked to it. Aborted calls never got a reply, so nothing follows
+/// on from them. Errors out if the conversation doesn't exist.
+pub fn get_latest_call_id(
+    conn: &mut SqliteConnection,
+    conversation_id: &EntityId,
+) -> ZammResult<Option<EntityId>> {
+    conversations::table
+        .find(conversation_id)
+        .select(conversations::id)
+        .first::<EntityId>(conn)?;
+    let latest_call_id = llm_calls::table
+        .filter(llm_calls::conversation_id.eq(conversation_id))
+        .filter(
+            llm_calls::finish_reason
+                .is_null()
+                .or(llm_calls::finish_reason.ne(FinishReason::Aborted)),
+        )
+        .order(llm_calls::timestamp.desc())
+        .select(llm_calls::id)
+        .first::<EntityId>(conn)
+        .optional()?;
+    Ok(latest_call_id)
+}
+
+/// Marks a conversation as having been updated at the given time.
+pub fn touch_conversation(
+    conn: &mut SqliteConnection,
+    conversation_id: &EntityId,
+    timestamp: &NaiveDateTime,
+) -> ZammResult<()> {
+    diesel::update(conversations::table.find(conversation_id))
+        .set(conversations::updated_at.eq(timestamp))
+        .execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::conversations::Conversation;
+    use crate::ZammDatabase;
+    use uuid::Uuid;
+
+    pub async fn insert_conversation(
+        zamm_db: &ZammDatabase,
+        title: &str,
+        timestamp: &str,
+    ) -> Conversation {
+        let timestamp =
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S").unwrap();
+        let conversation = Conversation {
+            id: EntityId {
+                uuid: Uuid::new_v4(),
+            },
+            title: title.to_string(),
+            created_at: timestamp,
+            updated_at: timestamp,
+        };
+        diesel::insert_into(conversations::table)
+            .values(conversation.as_sql_row())
+            .execute(&mut *zamm_db.connection().unwrap())
+            .unwrap();
+        conversation
+    }
+}
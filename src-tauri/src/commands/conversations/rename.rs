@@ -0,0 +1,74 @@
+use crate::commands::errors::ZammResult;
+use crate::models::conversations::Conversation;
+use crate::models::llm_calls::EntityId;
+use crate::schema::conversations;
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+async fn rename_conversation_helper(
+    zamm_db: &ZammDatabase,
+    id: Uuid,
+    title: String,
+) -> ZammResult<Conversation> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let conversation_id = EntityId { uuid: id };
+    let now = chrono::Utc::now().naive_utc();
+    let num_updated = diesel::update(conversations::table.find(&conversation_id))
+        .set((
+            conversations::title.eq(title),
+            conversations::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+    if num_updated == 0 {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    let conversation = conversations::table
+        .find(&conversation_id)
+        .select(Conversation::as_select())
+        .first(conn)?;
+    Ok(conversation)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn rename_conversation(
+    database: State<'_, ZammDatabase>,
+    id: Uuid,
+    title: String,
+) -> ZammResult<Conversation> {
+    rename_conversation_helper(&database, id, title).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::test_helpers::setup_zamm_db;
+
+    #[tokio::test]
+    async fn test_rename() {
+        let db = setup_zamm_db();
+        let original = insert_conversation(&db, "Old", "2024-03-01T10:00:00").await;
+
+        let renamed = rename_conversation_helper(&db, original.id.uuid, "New".into())
+            .await
+            .unwrap();
+        assert_eq!(renamed.id, original.id);
+        assert_eq!(renamed.title, "New");
+        assert_eq!(renamed.created_at, original.created_at);
+        assert!(renamed.updated_at > original.updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_rename_nonexistent() {
+        let db = setup_zamm_db();
+        let result =
+            rename_conversation_helper(&db, Uuid::new_v4(), "New".into()).await;
+        assert!(result.is_err());
+    }
+}
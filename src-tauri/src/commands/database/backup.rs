@@ -0,0 +1,62 @@
+use crate::commands::errors::ZammResult;
+use crate::setup::db::absolute_db_path;
+use crate::ZammDatabase;
+use specta::specta;
+use tauri::State;
+
+fn backup_database_helper(
+    zamm_db: &ZammDatabase,
+    path: Option<&str>,
+) -> ZammResult<String> {
+    let backup_path = zamm_db.backup(path.map(absolute_db_path))?;
+    Ok(backup_path.display().to_string())
+}
+
+/// Saves a copy of the database to the given file, or else to a timestamped file
+/// in the backups directory next to the database. Returns where the copy went.
+#[tauri::command(async)]
+#[specta]
+pub async fn backup_database(
+    database: State<'_, ZammDatabase>,
+    path: Option<String>,
+) -> ZammResult<String> {
+    backup_database_helper(&database, path.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Error;
+    use crate::setup::backups::validate_backup;
+    use crate::test_helpers::{get_temp_test_dir, setup_zamm_db};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_timestamped_backup() {
+        let db = setup_zamm_db();
+        let backup_path = PathBuf::from(backup_database_helper(&db, None).unwrap());
+        assert_eq!(
+            backup_path.parent().unwrap().file_name().unwrap(),
+            "backups"
+        );
+        validate_backup(&backup_path).unwrap();
+    }
+
+    #[test]
+    fn test_backup_to_chosen_file() {
+        let test_dir = get_temp_test_dir("backup_to_chosen_file");
+        let chosen_path = test_dir.join("before-cleanup.sqlite3");
+        let db = setup_zamm_db();
+
+        let backup_path = backup_database_helper(&db, chosen_path.to_str()).unwrap();
+        assert_eq!(backup_path, chosen_path.display().to_string());
+        validate_backup(&chosen_path).unwrap();
+    }
+
+    #[test]
+    fn test_backup_without_database() {
+        let db = ZammDatabase::new(None, None);
+        let result = backup_database_helper(&db, None);
+        assert!(matches!(result, Err(Error::MissingDatabase {})));
+    }
+}
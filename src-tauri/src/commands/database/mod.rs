@@ -0,0 +1,11 @@
+mod backup;
+mod reconnect;
+mod restore;
+mod status;
+mod switch;
+
+pub use backup::backup_database;
+pub use reconnect::reconnect_database;
+pub use restore::restore_database;
+pub use status::{get_database_status, DatabaseStatus};
+pub use switch::switch_database;
@@ -0,0 +1,48 @@
+use crate::commands::database::status::get_database_status_helper;
+use crate::commands::database::DatabaseStatus;
+use crate::commands::errors::ZammResult;
+use crate::ZammDatabase;
+use specta::specta;
+use tauri::State;
+
+fn reconnect_database_helper(zamm_db: &ZammDatabase) -> ZammResult<DatabaseStatus> {
+    // a failed attempt still gets reported through the status
+    let _ = zamm_db.reconnect();
+    get_database_status_helper(zamm_db)
+}
+
+/// Tries to open the database again, for when it was unavailable earlier.
+#[tauri::command(async)]
+#[specta]
+pub async fn reconnect_database(
+    database: State<'_, ZammDatabase>,
+) -> ZammResult<DatabaseStatus> {
+    reconnect_database_helper(&database)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::database::status::tests::latest_migration;
+    use crate::test_helpers::get_temp_test_dir;
+    use std::fs;
+
+    #[test]
+    fn test_reconnect_after_failure() {
+        let test_dir = get_temp_test_dir("reconnect_after_failure");
+        // SQLite won't create missing parent directories
+        let db_dir = test_dir.join("not-yet-created");
+        let db = ZammDatabase::open(db_dir.join("zamm.sqlite3"));
+
+        let status = reconnect_database_helper(&db).unwrap();
+        assert!(!status.available);
+        assert_eq!(status.migration_version, None);
+        assert!(status.last_error.is_some());
+
+        fs::create_dir_all(&db_dir).unwrap();
+        let status = reconnect_database_helper(&db).unwrap();
+        assert!(status.available);
+        assert_eq!(status.migration_version, Some(latest_migration()));
+        assert_eq!(status.last_error, None);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::commands::database::status::get_database_status_helper;
+use crate::commands::database::DatabaseStatus;
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::setup_api_keys;
+use crate::setup::db::absolute_db_path;
+use crate::setup::secrets::SecretStore;
+use crate::{ZammApiKeys, ZammDatabase, ZammSecrets};
+use specta::specta;
+use tauri::State;
+
+async fn restore_database_helper(
+    zamm_db: &ZammDatabase,
+    zamm_api_keys: &ZammApiKeys,
+    secrets: &dyn SecretStore,
+    path: &str,
+) -> ZammResult<DatabaseStatus> {
+    zamm_db.restore_from(&absolute_db_path(path))?;
+    // the backup may well have different keys saved in it
+    *zamm_api_keys.0.lock().await = setup_api_keys(zamm_db, secrets);
+    get_database_status_helper(zamm_db)
+}
+
+/// Replaces the database with a backup of it, once the backup has been checked to
+/// be a database that this version of ZAMM can use. The database gets backed up
+/// before being replaced.
+#[tauri::command(async)]
+#[specta]
+pub async fn restore_database(
+    database: State<'_, ZammDatabase>,
+    api_keys: State<'_, ZammApiKeys>,
+    secrets: State<'_, ZammSecrets>,
+    path: String,
+) -> ZammResult<DatabaseStatus> {
+    restore_database_helper(&database, &api_keys, secrets.0.as_ref(), &path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::commands::database::status::tests::latest_migration;
+    use crate::commands::Error;
+    use crate::schema::conversations;
+    use crate::setup::api_keys::ApiKeys;
+    use crate::setup::secrets::MemoryStore;
+    use crate::test_helpers::{get_temp_test_dir, setup_zamm_db};
+    use diesel::prelude::*;
+    use std::fs;
+    use tokio::sync::Mutex;
+
+    fn count_conversations(db: &ZammDatabase) -> i64 {
+        conversations::table
+            .count()
+            .get_result(&mut *db.connection().unwrap())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup() {
+        let db = setup_zamm_db();
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+        insert_conversation(&db, "Kept", "2024-04-01T09:00:00").await;
+        let backup_path = db.backup(None).unwrap();
+        insert_conversation(&db, "Lost", "2024-04-02T09:00:00").await;
+        assert_eq!(count_conversations(&db), 2);
+
+        let status = restore_database_helper(
+            &db,
+            &api_keys,
+            &MemoryStore::default(),
+            backup_path.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(status.available);
+        assert_eq!(status.migration_version, Some(latest_migration()));
+        assert_eq!(count_conversations(&db), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_backup_rejected() {
+        let test_dir = get_temp_test_dir("invalid_backup_rejected");
+        let not_a_backup = test_dir.join("notes.txt");
+        fs::write(&not_a_backup, "definitely not SQLite").unwrap();
+        let db = setup_zamm_db();
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+        insert_conversation(&db, "Kept", "2024-04-01T09:00:00").await;
+
+        let result = restore_database_helper(
+            &db,
+            &api_keys,
+            &MemoryStore::default(),
+            not_a_backup.to_str().unwrap(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::InvalidBackup { .. })));
+        assert_eq!(count_conversations(&db), 1);
+    }
+}
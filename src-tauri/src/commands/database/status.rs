@@ -0,0 +1,82 @@
+use crate::commands::errors::ZammResult;
+use crate::ZammDatabase;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::State;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct DatabaseStatus {
+    pub path: Option<String>,
+    /// Whether the app currently has a working connection to the database.
+    pub available: bool,
+    /// The most recent migration applied to the database.
+    pub migration_version: Option<String>,
+    pub last_error: Option<String>,
+}
+
+pub fn get_database_status_helper(
+    zamm_db: &ZammDatabase,
+) -> ZammResult<DatabaseStatus> {
+    let migration_version = zamm_db.migration_version().unwrap_or_else(|e| {
+        zamm_db.record_error(&e);
+        None
+    });
+    Ok(DatabaseStatus {
+        path: zamm_db.path()?.map(|path| path.display().to_string()),
+        available: zamm_db.is_available()?,
+        migration_version,
+        last_error: zamm_db.last_error()?,
+    })
+}
+
+/// Reports on the database that the app is using, including any problems with it.
+#[tauri::command(async)]
+#[specta]
+pub async fn get_database_status(
+    database: State<'_, ZammDatabase>,
+) -> ZammResult<DatabaseStatus> {
+    get_database_status_helper(&database)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::setup::db::MIGRATIONS;
+    use crate::test_helpers::setup_zamm_db;
+    use diesel::migration::MigrationSource;
+    use diesel::sqlite::Sqlite;
+
+    pub fn latest_migration() -> String {
+        MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+            .unwrap()
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_available_database_status() {
+        let db = setup_zamm_db();
+        let status = get_database_status_helper(&db).unwrap();
+        assert!(status.available);
+        assert!(status.path.unwrap().ends_with(".sqlite3"));
+        assert_eq!(status.migration_version, Some(latest_migration()));
+        assert_eq!(status.last_error, None);
+    }
+
+    #[test]
+    fn test_missing_database_status() {
+        let db = ZammDatabase::new(None, None);
+        let status = get_database_status_helper(&db).unwrap();
+        assert_eq!(
+            status,
+            DatabaseStatus {
+                path: None,
+                available: false,
+                migration_version: None,
+                last_error: None,
+            }
+        );
+    }
+}
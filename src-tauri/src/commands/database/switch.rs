@@ -0,0 +1,171 @@
+use crate::commands::database::status::get_database_status_helper;
+use crate::commands::database::DatabaseStatus;
+use crate::commands::errors::ZammResult;
+use crate::commands::preferences::{set_preferences_helper, Preferences};
+use crate::setup::api_keys::setup_api_keys;
+use crate::setup::db::absolute_db_path;
+use crate::setup::secrets::SecretStore;
+use crate::{ZammApiKeys, ZammDatabase, ZammSecrets};
+use specta::specta;
+use std::path::PathBuf;
+use tauri::State;
+
+async fn switch_database_helper(
+    zamm_db: &ZammDatabase,
+    zamm_api_keys: &ZammApiKeys,
+    secrets: &dyn SecretStore,
+    preferences_dir: &Option<PathBuf>,
+    path: &str,
+    remember: Option<bool>,
+) -> ZammResult<DatabaseStatus> {
+    let db_path = absolute_db_path(path);
+    zamm_db.switch_to(db_path.clone())?;
+    // the new database may still have plaintext keys to move out of it
+    *zamm_api_keys.0.lock().await = setup_api_keys(zamm_db, secrets);
+
+    if remember.unwrap_or(false) {
+        set_preferences_helper(
+            preferences_dir,
+            &Preferences {
+                database_path: Some(db_path.display().to_string()),
+                ..Preferences::default()
+            },
+        )?;
+    }
+    get_database_status_helper(zamm_db)
+}
+
+/// Starts using the database file at the given path, creating it if need be. The
+/// current database stays in use if the new one can't be opened or migrated.
+#[tauri::command(async)]
+#[specta]
+pub async fn switch_database(
+    app_handle: tauri::AppHandle,
+    database: State<'_, ZammDatabase>,
+    api_keys: State<'_, ZammApiKeys>,
+    secrets: State<'_, ZammSecrets>,
+    path: String,
+    remember: Option<bool>,
+) -> ZammResult<DatabaseStatus> {
+    let preferences_dir = app_handle.path_resolver().app_config_dir();
+    switch_database_helper(
+        &database,
+        &api_keys,
+        secrets.0.as_ref(),
+        &preferences_dir,
+        &path,
+        remember,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::preferences::get_preferences_helper;
+    use crate::commands::Error;
+    use crate::models::NewApiKey;
+    use crate::schema::api_keys;
+    use crate::setup::api_keys::{ApiKeys, Service};
+    use crate::setup::secrets::MemoryStore;
+    use crate::test_helpers::{get_temp_test_dir, setup_zamm_db};
+    use diesel::prelude::*;
+    use tokio::sync::Mutex;
+
+    fn empty_api_keys() -> ZammApiKeys {
+        ZammApiKeys(Mutex::new(ApiKeys::default()))
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_new_database() {
+        let test_dir = get_temp_test_dir("switch_to_new_database");
+        let db = setup_zamm_db();
+        let new_db_path = test_dir.join("work.sqlite3");
+
+        let status = switch_database_helper(
+            &db,
+            &empty_api_keys(),
+            &MemoryStore::default(),
+            &Some(test_dir.clone()),
+            new_db_path.to_str().unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(status.available);
+        assert_eq!(status.path, Some(new_db_path.display().to_string()));
+        assert!(status.migration_version.is_some());
+        // the switch is only for this session unless asked otherwise
+        assert_eq!(get_preferences_helper(&Some(test_dir)).database_path, None);
+    }
+
+    #[test]
+    fn test_switch_reloads_keys_and_remembers() {
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let test_dir = get_temp_test_dir("switch_reloads_keys_and_remembers");
+            let new_db_path = test_dir.join("personal.sqlite3");
+            let personal_db = ZammDatabase::open(new_db_path.clone());
+            diesel::insert_into(api_keys::table)
+                .values(&NewApiKey {
+                    service: Service::OpenAI,
+                    api_key: "p3rs0n4l-k3y",
+                })
+                .execute(&mut *personal_db.connection().unwrap())
+                .unwrap();
+            drop(personal_db);
+
+            let db = setup_zamm_db();
+            let api_keys = ZammApiKeys(Mutex::new(ApiKeys {
+                openai: Some("w0rk-k3y".to_string()),
+                anthropic: None,
+                ..ApiKeys::default()
+            }));
+            futures::executor::block_on(switch_database_helper(
+                &db,
+                &api_keys,
+                &MemoryStore::default(),
+                &Some(test_dir.clone()),
+                new_db_path.to_str().unwrap(),
+                Some(true),
+            ))
+            .unwrap();
+
+            assert_eq!(
+                api_keys.0.try_lock().unwrap().openai,
+                Some("p3rs0n4l-k3y".to_string())
+            );
+            assert_eq!(
+                get_preferences_helper(&Some(test_dir)).database_path,
+                Some(new_db_path.display().to_string())
+            );
+        });
+    }
+
+    #[tokio::test]
+    async fn test_newer_database_rejected() {
+        let test_dir = get_temp_test_dir("newer_database_rejected");
+        let newer_db_path = test_dir.join("newer.sqlite3");
+        let newer_db = ZammDatabase::open(newer_db_path.clone());
+        let future_migration = "INSERT INTO __diesel_schema_migrations (version) \
+                                VALUES ('99990101000000')";
+        diesel::sql_query(future_migration)
+            .execute(&mut *newer_db.connection().unwrap())
+            .unwrap();
+        drop(newer_db);
+
+        let db = setup_zamm_db();
+        let original_path = db.path().unwrap();
+        let result = switch_database_helper(
+            &db,
+            &empty_api_keys(),
+            &MemoryStore::default(),
+            &Some(test_dir),
+            newer_db_path.to_str().unwrap(),
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UnknownMigration { .. })));
+        assert_eq!(db.path().unwrap(), original_path);
+        assert!(db.is_available().unwrap());
+    }
+}
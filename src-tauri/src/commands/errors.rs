@@ -1,3 +1,4 @@
+use crate::commands::preferences::BudgetPeriod;
 use crate::setup::api_keys::Service;
 use std::{fmt, sync::PoisonError};
 
@@ -95,6 +96,65 @@ pub enum Error {
     UnexpectedOpenAiResponse { reason: String },
     #[error("Missing API key for {service}")]
     MissingApiKey { service: Service },
+    #[error("Error from {service} API: {reason}")]
+    LlmApi { service: Service, reason: String },
+    #[error("{feature} is not supported for {service}")]
+    UnsupportedFeature { service: Service, feature: String },
+    #[error(
+        "{period} budget for {service} exceeded: spent ${used:.2} of ${limit:.2}, \
+        and this call could cost up to ${estimated:.2} more"
+    )]
+    CostBudgetExceeded {
+        service: Service,
+        period: BudgetPeriod,
+        used: f64,
+        estimated: f64,
+        limit: f64,
+    },
+    #[error(
+        "{period} budget for {service} exceeded: used {used} of {limit} tokens, \
+        and this call could use up to {estimated} more"
+    )]
+    TokenBudgetExceeded {
+        service: Service,
+        period: BudgetPeriod,
+        used: i64,
+        estimated: i64,
+        limit: i64,
+    },
+    #[error(
+        "The price of {model} is unknown, so it can't be kept within the {service} \
+        budget. Add it to the pricing in your preferences first."
+    )]
+    UnknownPrice { service: Service, model: String },
+    #[error("The {service} budget can't be kept track of without a database")]
+    BudgetUntracked { service: Service },
+    #[error("LLM call {id} was not cut off, so there is nothing to continue")]
+    NotTruncated { id: uuid::Uuid },
+    #[error("Invalid image: {reason}")]
+    InvalidImage { reason: String },
+    #[error("No tool named {name}")]
+    UnknownTool { name: String },
+    #[error("Model was still calling tools after {rounds} rounds")]
+    TooManyToolRounds { rounds: usize },
+    #[error("Chat request {id} was cancelled")]
+    Cancelled { id: uuid::Uuid },
+    #[error("No chat request {id} is in progress")]
+    NoActiveChat { id: uuid::Uuid },
+    #[error("Database is not available")]
+    MissingDatabase {},
+    #[error("Database has migration {version} from a newer version of ZAMM")]
+    UnknownMigration { version: String },
+    #[error("Not a usable ZAMM database backup: {reason}")]
+    InvalidBackup { reason: String },
+    #[error("Secret storage failed: {reason}")]
+    SecretStorage { reason: String },
+    #[error("{service} rejected the API key: {reason}")]
+    InvalidApiKey { service: Service, reason: String },
+    #[error("Incorrect passphrase for API keys")]
+    IncorrectPassphrase {},
+    #[error("API keys are locked until they are unlocked with their passphrase")]
+    ApiKeysLocked {},
     #[error("Lock poisoned")]
     Poison {},
     #[error(transparent)]
@@ -113,11 +173,26 @@ pub enum Error {
         source: diesel::result::Error,
     },
     #[error(transparent)]
+    Pool {
+        #[from]
+        source: diesel::r2d2::PoolError,
+    },
+    #[error(transparent)]
+    Keyring {
+        #[from]
+        source: keyring::Error,
+    },
+    #[error(transparent)]
     Reqwest {
         #[from]
         source: reqwest::Error,
     },
     #[error(transparent)]
+    ReqwestMiddleware {
+        #[from]
+        source: reqwest_middleware::Error,
+    },
+    #[error(transparent)]
     OpenAI {
         #[from]
         source: async_openai::error::OpenAIError,
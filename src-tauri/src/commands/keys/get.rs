@@ -1,17 +1,20 @@
 use crate::commands::errors::ZammResult;
-use crate::setup::api_keys::ApiKeys;
+use crate::setup::api_keys::MaskedApiKeys;
 use crate::ZammApiKeys;
 use specta::specta;
-use std::clone::Clone;
 use tauri::State;
 
-async fn get_api_keys_helper(zamm_api_keys: &ZammApiKeys) -> ApiKeys {
-    zamm_api_keys.0.lock().await.clone()
+async fn get_api_keys_helper(zamm_api_keys: &ZammApiKeys) -> MaskedApiKeys {
+    zamm_api_keys.0.lock().await.masked()
 }
 
+/// Describes which API keys are set without giving away the keys themselves, which
+/// only `reveal_api_key` does.
 #[tauri::command(async)]
 #[specta]
-pub async fn get_api_keys(api_keys: State<'_, ZammApiKeys>) -> ZammResult<ApiKeys> {
+pub async fn get_api_keys(
+    api_keys: State<'_, ZammApiKeys>,
+) -> ZammResult<MaskedApiKeys> {
     Ok(get_api_keys_helper(&api_keys).await)
 }
 
@@ -19,6 +22,7 @@ pub async fn get_api_keys(api_keys: State<'_, ZammApiKeys>) -> ZammResult<ApiKey
 pub mod tests {
     use super::*;
     use crate::sample_call::SampleCall;
+    use crate::setup::api_keys::{ApiKeySource, ApiKeys};
     use tokio::sync::Mutex;
 
     use std::fs;
@@ -42,6 +46,31 @@ pub mod tests {
         assert_eq!(actual_json, expected_json);
     }
 
+    /// Like `check_get_api_keys_sample`, but for keys whose source varies between
+    /// test runs.
+    pub async fn check_get_api_keys_sample_without_sources(
+        file_prefix: &str,
+        rust_input: &ZammApiKeys,
+    ) {
+        let greet_sample = read_sample(file_prefix);
+        assert_eq!(greet_sample.request, vec!["get_api_keys"]);
+
+        let without_sources = |mut masked: MaskedApiKeys| {
+            for key in [&mut masked.openai, &mut masked.anthropic]
+                .into_iter()
+                .flatten()
+            {
+                key.source = None;
+            }
+            masked
+        };
+        let actual_result = without_sources(get_api_keys_helper(rust_input).await);
+        let expected_result = without_sources(
+            serde_json::from_str(&greet_sample.response.message).unwrap(),
+        );
+        assert_eq!(actual_result, expected_result);
+    }
+
     #[tokio::test]
     async fn test_get_empty_keys() {
         let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
@@ -57,6 +86,8 @@ pub mod tests {
     async fn test_get_openai_key() {
         let api_keys = ZammApiKeys(Mutex::new(ApiKeys {
             openai: Some("0p3n41-4p1-k3y".to_string()),
+            openai_source: Some(ApiKeySource::SecretStore),
+            ..ApiKeys::default()
         }));
 
         check_get_api_keys_sample(
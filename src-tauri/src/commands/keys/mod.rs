@@ -1,15 +1,22 @@
 mod get;
+mod reveal;
 mod set;
+mod unlock;
+mod validate;
 
 pub use get::get_api_keys;
+pub use reveal::reveal_api_key;
 pub use set::set_api_key;
+pub use unlock::unlock_api_keys;
+pub use validate::validate_api_key;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::setup::api_keys::ApiKeys;
+    use crate::setup::secrets::MemoryStore;
     use crate::ZammApiKeys;
-    use get::tests::check_get_api_keys_sample;
+    use get::tests::check_get_api_keys_sample_without_sources;
     use set::tests::{check_set_api_key_sample, setup_zamm_db};
     use tokio::sync::Mutex;
 
@@ -19,13 +26,16 @@ mod tests {
 
         check_set_api_key_sample(
             &setup_zamm_db(),
+            &MemoryStore::default(),
             "api/sample-calls/set_api_key-existing-no-newline.yaml",
             &api_keys,
             "api_keys_integration_tests",
         )
         .await;
 
-        check_get_api_keys_sample(
+        // the key's source is the shell init file it got exported to, which is in a
+        // different temp dir on every run
+        check_get_api_keys_sample_without_sources(
             "./api/sample-calls/get_api_keys-openai.yaml",
             &api_keys,
         )
@@ -0,0 +1,88 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use crate::setup::api_keys::Service;
+use crate::ZammApiKeys;
+use specta::specta;
+use tauri::State;
+
+async fn reveal_api_key_helper(
+    zamm_api_keys: &ZammApiKeys,
+    service: &Service,
+) -> ZammResult<String> {
+    let api_keys = zamm_api_keys.0.lock().await;
+    match api_keys.get(service) {
+        Some(api_key) => Ok(api_key.clone()),
+        // the key may well be one of the ones still locked away
+        None if api_keys.locked => Err(Error::ApiKeysLocked {}),
+        None => Err(Error::MissingApiKey {
+            service: service.clone(),
+        }),
+    }
+}
+
+/// Returns the full API key for a service, for when the user asks to see it.
+#[tauri::command(async)]
+#[specta]
+pub async fn reveal_api_key(
+    api_keys: State<'_, ZammApiKeys>,
+    service: Service,
+) -> ZammResult<String> {
+    reveal_api_key_helper(&api_keys, &service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_call::SampleCall;
+    use crate::setup::api_keys::ApiKeys;
+    use serde::{Deserialize, Serialize};
+    use std::fs;
+    use tokio::sync::Mutex;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct RevealApiKeyRequest {
+        service: Service,
+    }
+
+    fn read_sample(filename: &str) -> SampleCall {
+        let sample_str = fs::read_to_string(filename)
+            .unwrap_or_else(|_| panic!("No file found at {filename}"));
+        serde_yaml::from_str(&sample_str).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reveal_openai_key() {
+        let sample = read_sample("./api/sample-calls/reveal_api_key-openai.yaml");
+        assert_eq!(sample.request.len(), 2);
+        assert_eq!(sample.request[0], "reveal_api_key");
+        let request: RevealApiKeyRequest =
+            serde_json::from_str(&sample.request[1]).unwrap();
+
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys {
+            openai: Some("0p3n41-4p1-k3y".to_string()),
+            ..ApiKeys::default()
+        }));
+        let result = reveal_api_key_helper(&api_keys, &request.service)
+            .await
+            .unwrap();
+        let actual_json = serde_json::to_string_pretty(&result).unwrap();
+        assert_eq!(actual_json, sample.response.message.trim());
+    }
+
+    #[tokio::test]
+    async fn test_reveal_missing_key() {
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+        let result = reveal_api_key_helper(&api_keys, &Service::Anthropic).await;
+        assert!(matches!(result, Err(Error::MissingApiKey { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reveal_locked_key() {
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys {
+            locked: true,
+            ..ApiKeys::default()
+        }));
+        let result = reveal_api_key_helper(&api_keys, &Service::OpenAI).await;
+        assert!(matches!(result, Err(Error::ApiKeysLocked {})));
+    }
+}
@@ -1,7 +1,13 @@
 use crate::commands::errors::ZammResult;
+use crate::commands::keys::validate::validate_before_saving;
+use crate::commands::llms::retry::validation_http_client;
+use crate::commands::preferences::get_preferences_helper;
+use crate::commands::Error;
 use crate::schema::api_keys;
-use crate::setup::api_keys::Service;
-use crate::{ZammApiKeys, ZammDatabase};
+use crate::setup::api_keys::{env_api_key, ApiKeySource, Service};
+use crate::setup::secrets::passphrase::encrypt;
+use crate::setup::secrets::SecretStore;
+use crate::{ZammApiKeys, ZammDatabase, ZammSecrets};
 use diesel::{ExpressionMethods, RunQueryDsl};
 use specta::specta;
 use tauri::State;
@@ -10,20 +16,28 @@ use std::fs::OpenOptions;
 use std::io::{Read, Write};
 use std::path::Path;
 
-async fn set_api_key_helper(
+pub async fn set_api_key_helper(
     zamm_api_keys: &ZammApiKeys,
     zamm_db: &ZammDatabase,
+    secrets: &dyn SecretStore,
     filename: Option<&str>,
     service: &Service,
     api_key: String,
-) -> ZammResult<()> {
+) -> ZammResult<Option<String>> {
+    let env_var = service.env_var().ok_or(Error::UnsupportedFeature {
+        service: service.clone(),
+        feature: "API keys".to_string(),
+    })?;
     let api_keys = &mut zamm_api_keys.0.lock().await;
-    let db = &mut zamm_db.0.lock().await;
+    // a new key would otherwise get saved without the protection the old one had
+    if api_keys.locked && !api_key.is_empty() {
+        return Err(Error::ApiKeysLocked {});
+    }
 
     // write new API key to disk before we can no longer borrow it
-    let init_update_result = || -> ZammResult<()> {
+    let init_update_result = || -> ZammResult<Option<String>> {
         if api_key.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
         if let Some(untrimmed_filename) = filename {
@@ -48,54 +62,109 @@ async fn set_api_key_helper(
                 if !ends_in_newline {
                     writeln!(file)?;
                 }
-                writeln!(file, "export OPENAI_API_KEY=\"{}\"", api_key)?;
+                writeln!(file, "export {}=\"{}\"", env_var, api_key)?;
+                return Ok(Some(f.to_string()));
             }
         }
-        Ok(())
+        Ok(None)
     }();
 
-    // write new API key to database before we can no longer borrow it
-    let db_update_result = || -> ZammResult<()> {
-        if let Some(conn) = db.as_mut() {
-            if api_key.is_empty() {
-                // delete from db
+    // save new API key before we can no longer borrow it
+    let secrets_update_result = || -> ZammResult<()> {
+        if api_key.is_empty() {
+            secrets.delete(service)?;
+            // passphrase-protected keys, and plaintext ones from older versions of
+            // ZAMM, are kept in the database
+            if let Some(conn) = zamm_db.try_connection()?.as_deref_mut() {
                 diesel::delete(api_keys::table)
                     .filter(api_keys::service.eq(service))
                     .execute(conn)?;
-            } else {
-                diesel::replace_into(api_keys::table)
-                    .values(crate::models::NewApiKey {
-                        service: service.clone(),
-                        api_key: &api_key,
-                    })
-                    .execute(conn)?;
             }
+        } else if let Some(passphrase) = &api_keys.passphrase {
+            diesel::replace_into(api_keys::table)
+                .values(crate::models::NewApiKey {
+                    service: service.clone(),
+                    api_key: &encrypt(passphrase, &api_key)?,
+                })
+                .execute(&mut *zamm_db.connection()?)?;
+            // the protected key is the one that counts from now on
+            secrets.delete(service)?;
+        } else {
+            secrets.set(service, &api_key)?;
         }
         Ok(())
     }();
 
+    // the environment variable wins out again the next time ZAMM starts, unless
+    // it gets exported with the new key
+    let shadowed = !api_key.is_empty()
+        && !matches!(init_update_result, Ok(Some(_)))
+        && env_api_key(service).is_some_and(|env_key| env_key != api_key);
+    let warning = shadowed.then(|| {
+        format!(
+            "{env_var} is set in the environment, so it will override this key the \
+             next time ZAMM starts"
+        )
+    });
+
     // assign ownership of new API key string to in-memory API keys
     if api_key.is_empty() {
         api_keys.remove(service);
     } else {
-        api_keys.update(service, api_key);
+        let source = match (&init_update_result, &api_keys.passphrase) {
+            (Ok(Some(path)), _) => ApiKeySource::InitFile { path: path.clone() },
+            (_, Some(_)) => ApiKeySource::Database,
+            (_, None) => ApiKeySource::SecretStore,
+        };
+        api_keys.update(service, api_key, source);
     }
 
     // if any errors exist, return one of them
     init_update_result?;
-    db_update_result
+    secrets_update_result?;
+    Ok(warning)
 }
 
+/// Saves the API key for a service, returning a warning if the key is going to get
+/// overridden by the environment anyway. If asked to, the key gets checked with the
+/// provider first, and doesn't get saved if the provider rejects it.
+#[allow(clippy::too_many_arguments)]
 #[tauri::command(async)]
 #[specta]
 pub async fn set_api_key(
+    app_handle: tauri::AppHandle,
     api_keys: State<'_, ZammApiKeys>,
     database: State<'_, ZammDatabase>,
+    secrets: State<'_, ZammSecrets>,
     filename: Option<&str>,
     service: Service,
     api_key: String,
-) -> ZammResult<()> {
-    set_api_key_helper(&api_keys, &database, filename, &service, api_key).await
+    validate: Option<bool>,
+) -> ZammResult<Option<String>> {
+    // nothing is locked while waiting on the provider
+    let validation_warning = if validate.unwrap_or(false) && !api_key.is_empty() {
+        let preferences =
+            get_preferences_helper(&app_handle.path_resolver().app_config_dir());
+        let http_client = validation_http_client(&preferences)?;
+        validate_before_saving(&http_client, &service, &api_key).await?
+    } else {
+        None
+    };
+    let warning = set_api_key_helper(
+        &api_keys,
+        &database,
+        secrets.0.as_ref(),
+        filename,
+        &service,
+        api_key,
+    )
+    .await?;
+    Ok(match (validation_warning, warning) {
+        (Some(validation_warning), Some(warning)) => {
+            Some(format!("{validation_warning}\n{warning}"))
+        }
+        (validation_warning, warning) => validation_warning.or(warning),
+    })
 }
 
 #[cfg(test)]
@@ -105,25 +174,15 @@ pub mod tests {
     use crate::sample_call::SampleCall;
     use crate::schema;
     use crate::setup::api_keys::ApiKeys;
-    use crate::setup::db::MIGRATIONS;
+    use crate::setup::secrets::MemoryStore;
     use crate::test_helpers::get_temp_test_dir;
+    pub use crate::test_helpers::setup_zamm_db;
     use diesel::prelude::*;
-    use diesel_migrations::MigrationHarness;
     use serde::{Deserialize, Serialize};
     use std::fs;
     use std::path::{Path, PathBuf};
     use tokio::sync::Mutex;
 
-    fn setup_database() -> SqliteConnection {
-        let mut conn = SqliteConnection::establish(":memory:").unwrap();
-        conn.run_pending_migrations(MIGRATIONS).unwrap();
-        conn
-    }
-
-    pub fn setup_zamm_db() -> ZammDatabase {
-        ZammDatabase(Mutex::new(Some(setup_database())))
-    }
-
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     struct SetApiKeyRequest {
         filename: Option<String>,
@@ -143,8 +202,7 @@ pub mod tests {
 
     async fn get_openai_api_key_from_db(db: &ZammDatabase) -> Option<String> {
         use schema::api_keys::dsl::*;
-        let mut conn_mutex = db.0.lock().await;
-        let conn = conn_mutex.as_mut().unwrap();
+        let conn = &mut *db.connection().unwrap();
         api_keys
             .filter(service.eq(Service::OpenAI))
             .select(api_key)
@@ -154,6 +212,7 @@ pub mod tests {
 
     pub async fn check_set_api_key_sample(
         db: &ZammDatabase,
+        secrets: &dyn SecretStore,
         sample_file: &str,
         existing_zamm_api_keys: &ZammApiKeys,
         test_dir_name: &str,
@@ -193,6 +252,7 @@ pub mod tests {
         let actual_result = set_api_key_helper(
             existing_zamm_api_keys,
             db,
+            secrets,
             test_init_file.as_deref(),
             &request.service,
             request.api_key.clone(),
@@ -223,14 +283,16 @@ pub mod tests {
         let existing_api_keys = existing_zamm_api_keys.0.lock().await;
         if request.api_key.is_empty() {
             assert_eq!(existing_api_keys.openai, None);
-            assert_eq!(get_openai_api_key_from_db(db).await, None);
+            assert_eq!(secrets.get(&Service::OpenAI).unwrap(), None);
         } else {
             assert_eq!(existing_api_keys.openai, Some(request.api_key.clone()));
             assert_eq!(
-                get_openai_api_key_from_db(db).await,
+                secrets.get(&Service::OpenAI).unwrap(),
                 Some(request.api_key.clone())
             );
         }
+        // API keys never get written to the database in plaintext
+        assert_eq!(get_openai_api_key_from_db(db).await, None);
 
         // check that the API call successfully wrote the API keys to disk, if asked to
         if valid_request_path_specified {
@@ -260,6 +322,7 @@ pub mod tests {
     ) {
         check_set_api_key_sample(
             db,
+            &MemoryStore::default(),
             sample_file,
             existing_zamm_api_keys,
             "set_api_key",
@@ -316,18 +379,19 @@ pub mod tests {
         let dummy_key = "0p3n41-4p1-k3y";
         let api_keys = ZammApiKeys(Mutex::new(ApiKeys {
             openai: Some(dummy_key.to_string()),
+            ..ApiKeys::default()
         }));
-        let mut conn = setup_database();
+        let db = setup_zamm_db();
         diesel::insert_into(api_keys::table)
             .values(&NewApiKey {
                 service: Service::OpenAI,
                 api_key: dummy_key,
             })
-            .execute(&mut conn)
+            .execute(&mut *db.connection().unwrap())
             .unwrap();
 
         check_set_api_key_sample_unit(
-            &ZammDatabase(Mutex::new(Some(conn))),
+            &db,
             "api/sample-calls/set_api_key-unset.yaml",
             &api_keys,
         )
@@ -356,4 +420,49 @@ pub mod tests {
         )
         .await;
     }
+
+    #[test]
+    fn test_env_shadowed_key_warns() {
+        temp_env::with_var("OPENAI_API_KEY", Some("3nv-4p1-k3y"), || {
+            let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+            let warning = futures::executor::block_on(set_api_key_helper(
+                &api_keys,
+                &setup_zamm_db(),
+                &MemoryStore::default(),
+                None,
+                &Service::OpenAI,
+                "0p3n41-4p1-k3y".to_string(),
+            ))
+            .unwrap();
+            assert!(warning.unwrap().contains("OPENAI_API_KEY"));
+            let api_keys = api_keys.0.try_lock().unwrap();
+            assert_eq!(api_keys.openai_source, Some(ApiKeySource::SecretStore));
+        });
+    }
+
+    #[test]
+    fn test_exported_key_not_shadowed() {
+        temp_env::with_var("OPENAI_API_KEY", Some("3nv-4p1-k3y"), || {
+            let init_file = get_temp_test_dir("exported_key_not_shadowed")
+                .join(".bashrc")
+                .display()
+                .to_string();
+            let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+            let warning = futures::executor::block_on(set_api_key_helper(
+                &api_keys,
+                &setup_zamm_db(),
+                &MemoryStore::default(),
+                Some(&init_file),
+                &Service::OpenAI,
+                "0p3n41-4p1-k3y".to_string(),
+            ))
+            .unwrap();
+            assert_eq!(warning, None);
+            let api_keys = api_keys.0.try_lock().unwrap();
+            assert_eq!(
+                api_keys.openai_source,
+                Some(ApiKeySource::InitFile { path: init_file })
+            );
+        });
+    }
 }
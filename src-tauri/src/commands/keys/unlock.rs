@@ -0,0 +1,162 @@
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::{
+    env_api_key, load_db_api_keys, ApiKeySource, MaskedApiKeys,
+};
+use crate::setup::secrets::passphrase::{decrypt, is_encrypted, Passphrase};
+use crate::{ZammApiKeys, ZammDatabase};
+use specta::specta;
+use tauri::State;
+
+async fn unlock_api_keys_helper(
+    zamm_api_keys: &ZammApiKeys,
+    zamm_db: &ZammDatabase,
+    passphrase: String,
+) -> ZammResult<MaskedApiKeys> {
+    let passphrase = Passphrase::new(passphrase);
+    // nothing gets unlocked unless every key can be
+    let mut unlocked = vec![];
+    for api_key in load_db_api_keys(zamm_db)? {
+        if is_encrypted(&api_key.api_key) {
+            let plaintext = decrypt(&passphrase, &api_key.api_key)?;
+            unlocked.push((api_key.service, plaintext));
+        }
+    }
+
+    let mut api_keys = zamm_api_keys.0.lock().await;
+    for (service, plaintext) in unlocked {
+        // environment keys still take precedence
+        if env_api_key(&service).is_none() {
+            api_keys.update(&service, plaintext, ApiKeySource::Database);
+        }
+    }
+    api_keys.locked = false;
+    api_keys.passphrase = Some(passphrase);
+    Ok(api_keys.masked())
+}
+
+/// Decrypts the passphrase-protected API keys in the database. If none have been
+/// protected yet, keys that get set from now on are protected with this
+/// passphrase instead of going to the OS keyring.
+#[tauri::command(async)]
+#[specta]
+pub async fn unlock_api_keys(
+    api_keys: State<'_, ZammApiKeys>,
+    database: State<'_, ZammDatabase>,
+    passphrase: String,
+) -> ZammResult<MaskedApiKeys> {
+    unlock_api_keys_helper(&api_keys, &database, passphrase).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::keys::set::set_api_key_helper;
+    use crate::commands::Error;
+    use crate::schema::api_keys;
+    use crate::setup::api_keys::{setup_api_keys, ApiKeys, Service};
+    use crate::setup::secrets::{MemoryStore, SecretStore};
+    use crate::test_helpers::setup_zamm_db;
+    use diesel::prelude::*;
+    use tokio::sync::Mutex;
+
+    const DUMMY_API_KEY: &str = "0p3n41-4p1-k3y";
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn stored_openai_key(db: &ZammDatabase) -> String {
+        api_keys::table
+            .filter(api_keys::service.eq(Service::OpenAI))
+            .select(api_keys::api_key)
+            .first(&mut *db.connection().unwrap())
+            .unwrap()
+    }
+
+    /// Protects a key with the passphrase, as if in an earlier session.
+    async fn protect_key(db: &ZammDatabase, secrets: &MemoryStore) {
+        let api_keys = ZammApiKeys(Mutex::new(ApiKeys::default()));
+        unlock_api_keys_helper(&api_keys, db, PASSPHRASE.to_string())
+            .await
+            .unwrap();
+        set_api_key_helper(
+            &api_keys,
+            db,
+            secrets,
+            None,
+            &Service::OpenAI,
+            DUMMY_API_KEY.to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_protected_keys_start_locked() {
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let db = setup_zamm_db();
+            let secrets = MemoryStore::default();
+            futures::executor::block_on(protect_key(&db, &secrets));
+            assert!(!stored_openai_key(&db).contains(DUMMY_API_KEY));
+            assert_eq!(secrets.get(&Service::OpenAI).unwrap(), None);
+
+            let locked = setup_api_keys(&db, &secrets);
+            assert!(locked.locked);
+            assert_eq!(locked.openai, None);
+
+            let api_keys = ZammApiKeys(Mutex::new(locked));
+            let unlocked = futures::executor::block_on(unlock_api_keys_helper(
+                &api_keys,
+                &db,
+                PASSPHRASE.to_string(),
+            ))
+            .unwrap();
+            assert!(!unlocked.locked);
+            assert!(unlocked.openai.is_some());
+            assert_eq!(
+                api_keys.0.try_lock().unwrap().openai,
+                Some(DUMMY_API_KEY.to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn test_wrong_passphrase_stays_locked() {
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let db = setup_zamm_db();
+            let secrets = MemoryStore::default();
+            futures::executor::block_on(protect_key(&db, &secrets));
+
+            let api_keys = ZammApiKeys(Mutex::new(setup_api_keys(&db, &secrets)));
+            let result = futures::executor::block_on(unlock_api_keys_helper(
+                &api_keys,
+                &db,
+                "incorrect horse".to_string(),
+            ));
+            assert!(matches!(result, Err(Error::IncorrectPassphrase {})));
+            let api_keys = api_keys.0.try_lock().unwrap();
+            assert!(api_keys.locked);
+            assert_eq!(api_keys.openai, None);
+        });
+    }
+
+    #[test]
+    fn test_set_while_locked_rejected() {
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let db = setup_zamm_db();
+            let secrets = MemoryStore::default();
+            futures::executor::block_on(protect_key(&db, &secrets));
+            let protected_key = stored_openai_key(&db);
+
+            let api_keys = ZammApiKeys(Mutex::new(setup_api_keys(&db, &secrets)));
+            let result = futures::executor::block_on(set_api_key_helper(
+                &api_keys,
+                &db,
+                &secrets,
+                None,
+                &Service::OpenAI,
+                "n3w-k3y".to_string(),
+            ));
+            assert!(matches!(result, Err(Error::ApiKeysLocked {})));
+            assert_eq!(stored_openai_key(&db), protected_key);
+            assert_eq!(secrets.get(&Service::OpenAI).unwrap(), None);
+        });
+    }
+}
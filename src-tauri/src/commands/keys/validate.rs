@@ -0,0 +1,224 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::anthropic::ANTHROPIC_VERSION;
+use crate::commands::llms::retry::validation_http_client;
+use crate::commands::preferences::get_preferences_helper;
+use crate::commands::Error;
+use crate::setup::api_keys::Service;
+use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use specta::Type;
+
+/// Listing models is free, and works with any key that can make API calls at all.
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
+const ANTHROPIC_MODELS_URL: &str = "https://api.anthropic.com/v1/models";
+
+/// What the provider made of an API key.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Type)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ApiKeyValidation {
+    Valid,
+    /// The provider refused to authenticate the key.
+    Invalid {
+        reason: String,
+    },
+    /// The provider couldn't be reached, had problems of its own or is rate limiting
+    /// requests, so there's no telling whether the key works. Listing models doesn't
+    /// use up any quota, so whether the key has any left can't be told either.
+    NetworkError {
+        reason: String,
+    },
+}
+
+/// OpenAI and Anthropic both describe errors this way.
+#[derive(Deserialize)]
+struct ProviderErrorResponse {
+    error: ProviderError,
+}
+
+#[derive(Deserialize)]
+struct ProviderError {
+    message: String,
+}
+
+pub async fn validate_api_key_helper(
+    http_client: &ClientWithMiddleware,
+    service: &Service,
+    api_key: &str,
+) -> ZammResult<ApiKeyValidation> {
+    let request = match service {
+        Service::OpenAI => http_client.get(OPENAI_MODELS_URL).bearer_auth(api_key),
+        Service::Anthropic => http_client
+            .get(ANTHROPIC_MODELS_URL)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION),
+        Service::Ollama => {
+            return Err(Error::UnsupportedFeature {
+                service: service.clone(),
+                feature: "API keys".to_string(),
+            })
+        }
+    };
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(ApiKeyValidation::NetworkError {
+                reason: e.to_string(),
+            })
+        }
+    };
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(ApiKeyValidation::Valid);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<ProviderErrorResponse>(&body)
+        .map(|e| e.error.message)
+        .unwrap_or(body);
+    let reason = format!("{status}: {message}");
+    Ok(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            ApiKeyValidation::Invalid { reason }
+        }
+        _ => ApiKeyValidation::NetworkError { reason },
+    })
+}
+
+/// Checks a key that is about to be saved, refusing it if the provider does. Any
+/// other problem with the check becomes a warning instead, because the key may
+/// well work once it clears up.
+pub async fn validate_before_saving(
+    http_client: &ClientWithMiddleware,
+    service: &Service,
+    api_key: &str,
+) -> ZammResult<Option<String>> {
+    match validate_api_key_helper(http_client, service, api_key).await? {
+        ApiKeyValidation::Valid => Ok(None),
+        ApiKeyValidation::Invalid { reason } => Err(Error::InvalidApiKey {
+            service: service.clone(),
+            reason,
+        }),
+        ApiKeyValidation::NetworkError { reason } => {
+            Ok(Some(format!("The key could not be checked: {reason}")))
+        }
+    }
+}
+
+/// Makes a cheap authenticated request to the provider to find out whether the key
+/// works, without saving it.
+#[tauri::command(async)]
+#[specta]
+pub async fn validate_api_key(
+    app_handle: tauri::AppHandle,
+    service: Service,
+    api_key: String,
+) -> ZammResult<ApiKeyValidation> {
+    let preferences =
+        get_preferences_helper(&app_handle.path_resolver().app_config_dir());
+    let http_client = validation_http_client(&preferences)?;
+    validate_api_key_helper(&http_client, &service, &api_key).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::setup_vcr;
+    use reqwest_middleware::{ClientBuilder, Middleware, Next};
+    use task_local_extensions::Extensions;
+
+    /// The `validate-*` cassettes were written by hand after the providers' error
+    /// documentation rather than recorded, which is why their request IDs are
+    /// placeholders. They should be re-recorded once there are keys to do so with.
+    async fn check_validation(
+        recording_path: &str,
+        service: Service,
+        api_key: &str,
+    ) -> ApiKeyValidation {
+        let (_, http_client) = setup_vcr(recording_path);
+        validate_api_key_helper(&http_client, &service, api_key)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_openai_key() {
+        let validation = check_validation(
+            "api/sample-call-requests/validate-openai-valid.json",
+            Service::OpenAI,
+            "dummy",
+        )
+        .await;
+        assert_eq!(validation, ApiKeyValidation::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_openai_key() {
+        let validation = check_validation(
+            "api/sample-call-requests/validate-openai-invalid.json",
+            Service::OpenAI,
+            "sk-n0t-4-r34l-k3y",
+        )
+        .await;
+        assert!(matches!(
+            validation,
+            ApiKeyValidation::Invalid { reason } if reason.contains("Incorrect API key")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_valid_anthropic_key() {
+        let validation = check_validation(
+            "api/sample-call-requests/validate-anthropic-valid.json",
+            Service::Anthropic,
+            "dummy",
+        )
+        .await;
+        assert_eq!(validation, ApiKeyValidation::Valid);
+    }
+
+    struct Unreachable;
+
+    #[async_trait::async_trait]
+    impl Middleware for Unreachable {
+        async fn handle(
+            &self,
+            _req: reqwest::Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> reqwest_middleware::Result<reqwest::Response> {
+            Err(reqwest_middleware::Error::Middleware(anyhow::anyhow!(
+                "Network is unreachable"
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_provider() {
+        let http_client = ClientBuilder::new(reqwest::Client::new())
+            .with(Unreachable)
+            .build();
+        let validation =
+            validate_api_key_helper(&http_client, &Service::OpenAI, "dummy")
+                .await
+                .unwrap();
+        assert!(matches!(validation, ApiKeyValidation::NetworkError { .. }));
+
+        // the key may still be saved, but not without saying so
+        let warning = validate_before_saving(&http_client, &Service::OpenAI, "dummy")
+            .await
+            .unwrap();
+        assert!(warning.unwrap().contains("Network is unreachable"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_key_not_saved() {
+        let (_, http_client) =
+            setup_vcr("api/sample-call-requests/validate-openai-invalid.json");
+        let result =
+            validate_before_saving(&http_client, &Service::OpenAI, "sk-n0t-4-r34l-k3y")
+                .await;
+        assert!(matches!(result, Err(Error::InvalidApiKey { .. })));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::chat::LlmCompletion;
+use crate::commands::Error;
+use crate::models::llm_calls::{
+    ChatImage, ChatMessage, ChatParameters, CompletionChoice, FinishReason,
+    ResponseFormat, TokenMetadata,
+};
+use crate::setup::api_keys::Service;
+use anyhow::anyhow;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+
+const ANTHROPIC_MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// The Messages API requires an explicit limit on the number of tokens generated.
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum AnthropicRole {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicImageSource {
+    Base64 { media_type: String, data: String },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RequestContentBlock {
+    Text { text: String },
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum AnthropicContent {
+    Text(String),
+    Blocks(Vec<RequestContentBlock>),
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: AnthropicRole,
+    content: AnthropicContent,
+}
+
+/// Images must already be loaded as base64 data.
+fn human_content(text: &str, images: &[ChatImage]) -> ZammResult<AnthropicContent> {
+    if images.is_empty() {
+        return Ok(AnthropicContent::Text(text.to_string()));
+    }
+
+    // Anthropic recommends putting images before the text that asks about them
+    let mut blocks = images
+        .iter()
+        .map(|image| match image {
+            ChatImage::Base64 { media_type, data } => Ok(RequestContentBlock::Image {
+                source: AnthropicImageSource::Base64 {
+                    media_type: media_type.clone(),
+                    data: data.clone(),
+                },
+            }),
+            _ => Err(Error::InvalidImage {
+                reason: "Image data must be loaded before it can be sent".to_string(),
+            }),
+        })
+        .collect::<ZammResult<Vec<RequestContentBlock>>>()?;
+    if !text.is_empty() {
+        blocks.push(RequestContentBlock::Text {
+            text: text.to_string(),
+        });
+    }
+    Ok(AnthropicContent::Blocks(blocks))
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage>,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    input_tokens: i32,
+    output_tokens: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    model: String,
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: Usage,
+}
+
+fn finish_reason(stop_reason: &str) -> Option<FinishReason> {
+    match stop_reason {
+        "end_turn" | "stop_sequence" => Some(FinishReason::Stop),
+        "max_tokens" => Some(FinishReason::Length),
+        "tool_use" => Some(FinishReason::ToolCalls),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetails {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetails,
+}
+
+/// Anthropic takes system prompts as a top-level parameter rather than as part of
+/// the conversation, so we pull those out of the prompt here.
+fn split_system_prompt(
+    prompt: &[ChatMessage],
+) -> ZammResult<(Option<String>, Vec<AnthropicMessage>)> {
+    let mut system_prompts = vec![];
+    let mut messages = vec![];
+    for message in prompt {
+        match message {
+            ChatMessage::System { text } => system_prompts.push(text.clone()),
+            ChatMessage::Human { text, images } => messages.push(AnthropicMessage {
+                role: AnthropicRole::User,
+                content: human_content(text, images)?,
+            }),
+            ChatMessage::AI { text } => messages.push(AnthropicMessage {
+                role: AnthropicRole::Assistant,
+                content: AnthropicContent::Text(text.clone()),
+            }),
+            ChatMessage::AIToolCalls { .. } | ChatMessage::Tool { .. } => {
+                return Err(Error::UnsupportedFeature {
+                    service: Service::Anthropic,
+                    feature: "Tool calling".to_string(),
+                })
+            }
+        }
+    }
+
+    let system = if system_prompts.is_empty() {
+        None
+    } else {
+        Some(system_prompts.join("\n\n"))
+    };
+    Ok((system, messages))
+}
+
+/// Errors out on parameters that the Messages API has no equivalent for, rather
+/// than silently ignoring them.
+fn check_supported(parameters: &ChatParameters) -> ZammResult<()> {
+    let unsupported = [
+        ("Presence penalty", parameters.presence_penalty.is_some()),
+        ("Frequency penalty", parameters.frequency_penalty.is_some()),
+        ("Seed", parameters.seed.is_some()),
+        ("Multiple completions", parameters.n.is_some_and(|n| n > 1)),
+        (
+            "JSON mode",
+            parameters.response_format == Some(ResponseFormat::JsonObject),
+        ),
+        (
+            "Tool calling",
+            parameters
+                .tools
+                .as_ref()
+                .is_some_and(|tools| !tools.0.is_empty()),
+        ),
+    ];
+    match unsupported.into_iter().find(|(_, is_set)| *is_set) {
+        Some((feature, _)) => Err(Error::UnsupportedFeature {
+            service: Service::Anthropic,
+            feature: feature.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+pub async fn chat_anthropic(
+    http_client: &ClientWithMiddleware,
+    api_key: &str,
+    model: &str,
+    temperature: f32,
+    prompt: &[ChatMessage],
+    parameters: &ChatParameters,
+) -> ZammResult<LlmCompletion> {
+    check_supported(parameters)?;
+    let max_tokens = match parameters.max_tokens {
+        Some(max_tokens) => u32::try_from(max_tokens).map_err(|e| anyhow!(e))?,
+        None => DEFAULT_MAX_TOKENS,
+    };
+    let (system, messages) = split_system_prompt(prompt)?;
+    let request = MessagesRequest {
+        model,
+        max_tokens,
+        system,
+        messages,
+        temperature,
+        top_p: parameters.top_p,
+        stop_sequences: parameters.stop.as_ref().map(|stop| stop.0.clone()),
+    };
+
+    let response = http_client
+        .post(ANTHROPIC_MESSAGES_URL)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .json(&request)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await?;
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.error.message)
+            .unwrap_or(body);
+        return Err(Error::LlmApi {
+            service: Service::Anthropic,
+            reason: format!("{status}: {message}"),
+        });
+    }
+
+    let response: MessagesResponse = response.json().await?;
+    let text = response
+        .content
+        .into_iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text),
+            ContentBlock::Other => None,
+        })
+        .collect::<Vec<String>>()
+        .join("");
+    let usage = response.usage;
+    Ok(LlmCompletion {
+        model: response.model,
+        choices: vec![CompletionChoice {
+            completion: ChatMessage::AI { text },
+            finish_reason: response.stop_reason.as_deref().and_then(finish_reason),
+        }],
+        tokens: TokenMetadata {
+            prompt: Some(usage.input_tokens),
+            response: Some(usage.output_tokens),
+            total: Some(usage.input_tokens + usage.output_tokens),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_prompt_moved_to_top_level() {
+        let prompt = vec![
+            ChatMessage::System {
+                text: "Be concise.".to_string(),
+            },
+            ChatMessage::Human {
+                text: "Hi".to_string(),
+                images: vec![],
+            },
+            ChatMessage::AI {
+                text: "Hello".to_string(),
+            },
+        ];
+
+        let (system, messages) = split_system_prompt(&prompt).unwrap();
+        assert_eq!(system, Some("Be concise.".to_string()));
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0].role, AnthropicRole::User));
+        assert!(matches!(messages[1].role, AnthropicRole::Assistant));
+    }
+
+    #[test]
+    fn test_images_sent_as_content_blocks() {
+        let prompt = vec![ChatMessage::Human {
+            text: "What is this?".to_string(),
+            images: vec![ChatImage::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0KGgo=".to_string(),
+            }],
+        }];
+
+        let (_, messages) = split_system_prompt(&prompt).unwrap();
+        assert_eq!(
+            serde_json::to_value(&messages[0].content).unwrap(),
+            serde_json::json!([
+                {
+                    "type": "image",
+                    "source": {
+                        "type": "base64",
+                        "media_type": "image/png",
+                        "data": "iVBORw0KGgo="
+                    }
+                },
+                {"type": "text", "text": "What is this?"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unsupported_parameters_rejected() {
+        let parameters = ChatParameters {
+            seed: Some(42),
+            ..ChatParameters::default()
+        };
+        assert_eq!(
+            check_supported(&parameters).unwrap_err().to_string(),
+            "Seed is not supported for anthropic"
+        );
+        assert!(check_supported(&ChatParameters::default()).is_ok());
+    }
+}
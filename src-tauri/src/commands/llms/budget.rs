@@ -0,0 +1,412 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::anthropic::DEFAULT_MAX_TOKENS;
+use crate::commands::llms::pricing::compute_cost;
+use crate::commands::preferences::{Budget, BudgetPeriod, Preferences};
+use crate::commands::Error;
+use crate::models::llm_calls::{ChatMessage, ChatParameters, Llm, TokenMetadata};
+use crate::schema::llm_calls;
+use crate::setup::api_keys::Service;
+use crate::ZammDatabase;
+use chrono::naive::NaiveDateTime;
+use chrono::Datelike;
+use diesel::dsl::{count, sum};
+use diesel::prelude::*;
+
+/// A rough average for English text. Prompts get measured in their JSON form, so
+/// estimates err on the high side.
+const CHARACTERS_PER_TOKEN: usize = 4;
+
+/// The most that a call could add to a budget, going by the size of its prompt and
+/// the number of tokens it may generate. There is no cost estimate for models
+/// without a known price.
+#[derive(Debug, Clone, Default)]
+pub struct CallEstimate {
+    pub model: String,
+    pub tokens: i64,
+    pub cost: Option<f64>,
+}
+
+impl CallEstimate {
+    /// OpenAI calls without `max_tokens` have no upper bound, so only their prompt
+    /// can be counted.
+    pub fn new(
+        preferences: &Preferences,
+        provider: &Service,
+        model: &str,
+        prompt: &[ChatMessage],
+        parameters: &ChatParameters,
+    ) -> Self {
+        let prompt_characters: usize = prompt
+            .iter()
+            .map(|message| serde_json::to_string(message).unwrap_or_default().len())
+            .sum();
+        let prompt_tokens = (prompt_characters / CHARACTERS_PER_TOKEN) as i32;
+        let max_tokens = match (parameters.max_tokens, provider) {
+            (Some(max_tokens), _) => max_tokens,
+            (None, Service::Anthropic) => DEFAULT_MAX_TOKENS as i32,
+            (None, _) => 0,
+        };
+        let response_tokens = max_tokens.saturating_mul(parameters.n.unwrap_or(1));
+        let tokens = TokenMetadata {
+            prompt: Some(prompt_tokens),
+            response: Some(response_tokens),
+            total: Some(prompt_tokens.saturating_add(response_tokens)),
+        };
+        let llm = Llm {
+            provider: provider.clone(),
+            name: model.to_string(),
+            requested: model.to_string(),
+        };
+        CallEstimate {
+            model: model.to_string(),
+            tokens: tokens.total.unwrap_or_default() as i64,
+            cost: compute_cost(preferences, &llm, &tokens),
+        }
+    }
+}
+
+fn period_start(period: BudgetPeriod, now: &NaiveDateTime) -> NaiveDateTime {
+    let today = now.date();
+    let start_date = match period {
+        BudgetPeriod::Daily => today,
+        BudgetPeriod::Monthly => today.with_day(1).unwrap_or(today),
+    };
+    start_date.and_hms_opt(0, 0, 0).unwrap_or(*now)
+}
+
+/// Returns a warning if the budget could already be used up without it showing,
+/// because some of the calls counted against it have no known cost.
+fn check_budget(
+    conn: &mut SqliteConnection,
+    budget: &Budget,
+    estimate: &CallEstimate,
+    now: &NaiveDateTime,
+) -> ZammResult<Option<String>> {
+    let calls_in_period = || {
+        llm_calls::table
+            .filter(llm_calls::provider.eq(&budget.provider))
+            .filter(llm_calls::timestamp.ge(period_start(budget.period, now)))
+    };
+    let (cost_used, tokens_used) = calls_in_period()
+        .select((sum(llm_calls::cost), sum(llm_calls::total_tokens)))
+        .first::<(Option<f64>, Option<i64>)>(conn)?;
+    let cost_used = cost_used.unwrap_or(0.0);
+    let tokens_used = tokens_used.unwrap_or(0);
+
+    let mut warning = None;
+    if let Some(max_cost) = budget.max_cost {
+        let estimated_cost = estimate.cost.ok_or_else(|| Error::UnknownPrice {
+            service: budget.provider.clone(),
+            model: estimate.model.clone(),
+        })?;
+        if cost_used >= max_cost || cost_used + estimated_cost > max_cost {
+            return Err(Error::CostBudgetExceeded {
+                service: budget.provider.clone(),
+                period: budget.period,
+                used: cost_used,
+                estimated: estimated_cost,
+                limit: max_cost,
+            });
+        }
+
+        let unpriced_calls = calls_in_period()
+            .filter(llm_calls::cost.is_null())
+            .select(count(llm_calls::id))
+            .first::<i64>(conn)?;
+        if unpriced_calls > 0 {
+            warning = Some(format!(
+                "{unpriced_calls} call(s) to {} have no known cost, so the {} \
+                budget may already be past the ${cost_used:.2} of ${max_cost:.2} \
+                counted so far",
+                budget.provider, budget.period
+            ));
+        }
+    }
+    if let Some(max_tokens) = budget.max_tokens {
+        if tokens_used >= max_tokens || tokens_used + estimate.tokens > max_tokens {
+            return Err(Error::TokenBudgetExceeded {
+                service: budget.provider.clone(),
+                period: budget.period,
+                used: tokens_used,
+                estimated: estimate.tokens,
+                limit: max_tokens,
+            });
+        }
+    }
+    Ok(warning)
+}
+
+/// Refuses a call to a provider if it could take the usage recorded for it so far
+/// past any of the budgets the user has configured for it. Calls to models without
+/// a known price are refused outright while there is a cost budget.
+pub fn check_budgets(
+    conn: &mut SqliteConnection,
+    preferences: &Preferences,
+    provider: &Service,
+    estimate: &CallEstimate,
+    now: &NaiveDateTime,
+) -> ZammResult<Option<String>> {
+    let mut warnings = vec![];
+    for budget in preferences.budgets.iter().flatten() {
+        if &budget.provider == provider {
+            warnings.extend(check_budget(conn, budget, estimate, now)?);
+        }
+    }
+    Ok((!warnings.is_empty()).then(|| warnings.join("\n")))
+}
+
+/// Checks the budgets for a call that is about to be made. Usage can't be tracked
+/// without a database, so there has to be one whenever budgets are set.
+pub fn check_call_budgets(
+    zamm_db: &ZammDatabase,
+    preferences: &Preferences,
+    provider: &Service,
+    estimate: &CallEstimate,
+) -> ZammResult<Option<String>> {
+    let has_budget = preferences
+        .budgets
+        .iter()
+        .flatten()
+        .any(|budget| &budget.provider == provider);
+    if !has_budget {
+        return Ok(None);
+    }
+    let mut conn = zamm_db.try_connection()?.ok_or(Error::BudgetUntracked {
+        service: provider.clone(),
+    })?;
+    let now = chrono::Utc::now().naive_utc();
+    check_budgets(&mut conn, preferences, provider, estimate, &now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::models::llm_calls::LlmCall;
+    use crate::test_helpers::{setup_zamm_db, TestDatabase};
+
+    fn timestamp(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    fn budget_preferences(period: BudgetPeriod, max_cost: f64) -> Preferences {
+        Preferences {
+            budgets: Some(vec![Budget {
+                provider: Service::OpenAI,
+                period,
+                max_cost: Some(max_cost),
+                max_tokens: None,
+            }]),
+            ..Preferences::default()
+        }
+    }
+
+    async fn check_estimate(
+        db: &ZammDatabase,
+        preferences: &Preferences,
+        provider: Service,
+        estimate: &CallEstimate,
+    ) -> ZammResult<Option<String>> {
+        let mut conn = db.connection().unwrap();
+        check_budgets(
+            &mut *conn,
+            preferences,
+            &provider,
+            estimate,
+            &timestamp("2024-03-15T12:00:00"),
+        )
+    }
+
+    async fn check(
+        db: &ZammDatabase,
+        preferences: &Preferences,
+        provider: Service,
+    ) -> ZammResult<Option<String>> {
+        let estimate = CallEstimate {
+            model: "gpt-4".to_string(),
+            tokens: 0,
+            cost: Some(0.0),
+        };
+        check_estimate(db, preferences, provider, &estimate).await
+    }
+
+    async fn setup_spend() -> TestDatabase {
+        let db = setup_zamm_db();
+        for (time, cost) in [("2024-03-02T10:00:00", 3.0), ("2024-03-15T10:00:00", 1.0)]
+        {
+            let llm_call = LlmCall {
+                cost: Some(cost),
+                ..read_sample_llm_call(
+                    "api/sample-calls/chat-start-conversation.yaml",
+                    time,
+                )
+            };
+            insert_llm_call(&db, &llm_call).await;
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_daily_budget() {
+        let db = setup_spend().await;
+        let within = budget_preferences(BudgetPeriod::Daily, 1.5);
+        assert!(check(&db, &within, Service::OpenAI).await.is_ok());
+
+        let exceeded = budget_preferences(BudgetPeriod::Daily, 1.0);
+        let result = check(&db, &exceeded, Service::OpenAI).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "daily budget for open_ai exceeded: spent $1.00 of $1.00, and this call \
+            could cost up to $0.00 more"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_call_counts_toward_budget() {
+        let db = setup_spend().await;
+        let preferences = budget_preferences(BudgetPeriod::Daily, 1.5);
+        let small_call = CallEstimate {
+            model: "gpt-4".to_string(),
+            tokens: 100,
+            cost: Some(0.25),
+        };
+        assert!(
+            check_estimate(&db, &preferences, Service::OpenAI, &small_call)
+                .await
+                .is_ok()
+        );
+
+        let large_call = CallEstimate {
+            model: "gpt-4".to_string(),
+            tokens: 100_000,
+            cost: Some(0.75),
+        };
+        let result =
+            check_estimate(&db, &preferences, Service::OpenAI, &large_call).await;
+        assert!(matches!(
+            result,
+            Err(Error::CostBudgetExceeded { used, estimated, limit, .. })
+                if used == 1.0 && estimated == 0.75 && limit == 1.5
+        ));
+    }
+
+    #[test]
+    fn test_estimate_counts_prompt_and_max_tokens() {
+        let prompt = vec![ChatMessage::Human {
+            text: "x".repeat(4000),
+            images: vec![],
+        }];
+        let parameters = ChatParameters {
+            max_tokens: Some(1000),
+            n: Some(2),
+            ..ChatParameters::default()
+        };
+        let estimate = CallEstimate::new(
+            &Preferences::default(),
+            &Service::OpenAI,
+            "gpt-4",
+            &prompt,
+            &parameters,
+        );
+        // the prompt is at least 1,000 tokens, and two completions at most 2,000
+        assert!(estimate.tokens >= 3000 && estimate.tokens < 3100);
+        // gpt-4 costs $30 per million prompt tokens and $60 per million response
+        // tokens
+        let cost = estimate.cost.unwrap();
+        assert!(cost >= 0.15 && cost < 0.16);
+    }
+
+    #[tokio::test]
+    async fn test_unpriced_model_refused_under_cost_budget() {
+        let db = setup_spend().await;
+        let preferences = budget_preferences(BudgetPeriod::Monthly, 100.0);
+        let estimate = CallEstimate::new(
+            &preferences,
+            &Service::OpenAI,
+            "gpt-4o",
+            &[],
+            &ChatParameters::default(),
+        );
+        let result =
+            check_estimate(&db, &preferences, Service::OpenAI, &estimate).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The price of gpt-4o is unknown, so it can't be kept within the open_ai \
+            budget. Add it to the pricing in your preferences first."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unpriced_past_calls_warned_about() {
+        let db = setup_spend().await;
+        let llm_call = LlmCall {
+            cost: None,
+            ..read_sample_llm_call(
+                "api/sample-calls/chat-start-conversation.yaml",
+                "2024-03-10T10:00:00",
+            )
+        };
+        insert_llm_call(&db, &llm_call).await;
+
+        let preferences = budget_preferences(BudgetPeriod::Monthly, 100.0);
+        let warning = check(&db, &preferences, Service::OpenAI).await.unwrap();
+        assert_eq!(
+            warning.as_deref(),
+            Some(
+                "1 call(s) to open_ai have no known cost, so the monthly budget may \
+                already be past the $4.00 of $100.00 counted so far"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_budget_needs_database() {
+        let preferences = budget_preferences(BudgetPeriod::Daily, 1.0);
+        let estimate = CallEstimate {
+            model: "gpt-4".to_string(),
+            tokens: 0,
+            cost: Some(0.0),
+        };
+        let no_db = ZammDatabase::new(None, None);
+        let result =
+            check_call_budgets(&no_db, &preferences, &Service::OpenAI, &estimate);
+        assert!(matches!(result, Err(Error::BudgetUntracked { .. })));
+        // but there's no need for one without a budget
+        let result = check_call_budgets(
+            &no_db,
+            &Preferences::default(),
+            &Service::OpenAI,
+            &estimate,
+        );
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_monthly_budget() {
+        let db = setup_spend().await;
+        let preferences = budget_preferences(BudgetPeriod::Monthly, 3.5);
+        assert!(check(&db, &preferences, Service::OpenAI).await.is_err());
+        // budgets only apply to the provider they are set for
+        assert!(check(&db, &preferences, Service::Anthropic).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_token_budget() {
+        let db = setup_spend().await;
+        let preferences = Preferences {
+            budgets: Some(vec![Budget {
+                provider: Service::OpenAI,
+                period: BudgetPeriod::Monthly,
+                max_cost: None,
+                max_tokens: Some(88),
+            }]),
+            ..Preferences::default()
+        };
+        let result = check(&db, &preferences, Service::OpenAI).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "monthly budget for open_ai exceeded: used 88 of 88 tokens, and this call \
+            could use up to 0 more"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::chat::{get_previous_call_id, record_llm_call};
+use crate::commands::llms::images::store_images;
+use crate::commands::Error;
+use crate::models::llm_calls::{
+    ChatMessage, ChatParameters, ChatPrompt, CompletionChoices, EntityId, FinishReason,
+    Llm, LlmCall, Prompt, Request, Response, TokenMetadata,
+};
+use crate::setup::api_keys::Service;
+use crate::setup::blobs::BlobStore;
+use crate::{ZammActiveChats, ZammDatabase};
+use futures::future::{AbortHandle, Abortable};
+use specta::specta;
+use std::future::Future;
+use tauri::State;
+use uuid::Uuid;
+
+/// A chat request that is still waiting on its LLM provider.
+pub struct ActiveChat {
+    abort_handle: AbortHandle,
+    record_aborted: bool,
+}
+
+/// The call that gets recorded in place of a chat request that was cancelled
+/// before the provider replied.
+pub fn aborted_call(
+    request_id: Uuid,
+    provider: &Service,
+    llm: &str,
+    temperature: Option<f32>,
+    prompt: &[ChatMessage],
+    parameters: Option<&ChatParameters>,
+    conversation_id: Option<Uuid>,
+) -> LlmCall {
+    LlmCall {
+        id: EntityId { uuid: request_id },
+        timestamp: chrono::Utc::now().naive_utc(),
+        llm: Llm {
+            provider: provider.clone(),
+            name: llm.to_string(),
+            requested: llm.to_string(),
+        },
+        request: Request {
+            temperature: temperature.unwrap_or(1.0),
+            prompt: Prompt::Chat(ChatPrompt {
+                messages: prompt.to_vec(),
+            }),
+            parameters: parameters.cloned().unwrap_or_default(),
+        },
+        response: Response {
+            completion: ChatMessage::AI {
+                text: String::new(),
+            },
+            finish_reason: Some(FinishReason::Aborted),
+            choices: CompletionChoices::default(),
+        },
+        tokens: TokenMetadata {
+            prompt: None,
+            response: None,
+            total: None,
+        },
+        cost: None,
+        conversation_id: conversation_id.map(|uuid| EntityId { uuid }),
+        previous_call_id: None,
+        warning: None,
+    }
+}
+
+/// Runs a request so that `cancel_chat` can abort it by its ID. An aborted request
+/// comes back as whether the canceller asked for a record of it to be kept.
+async fn run_abortable<T>(
+    active_chats: &ZammActiveChats,
+    request_id: Uuid,
+    request: impl Future<Output = T>,
+) -> Result<T, bool> {
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    active_chats.0.lock().await.insert(
+        request_id,
+        ActiveChat {
+            abort_handle,
+            record_aborted: false,
+        },
+    );
+    let result = Abortable::new(request, abort_registration).await;
+    let active_chat = active_chats.0.lock().await.remove(&request_id);
+    result.map_err(|_| active_chat.map(|chat| chat.record_aborted).unwrap_or(false))
+}
+
+/// Runs a chat request so that `cancel_chat` can abort it using the ID of the
+/// given aborted call, which gets recorded if the canceller asked for it. Any images
+/// in the aborted call's prompt only get stored once it is recorded.
+pub async fn cancellable_chat(
+    active_chats: &ZammActiveChats,
+    zamm_db: &ZammDatabase,
+    blob_store: &BlobStore,
+    aborted: LlmCall,
+    request: impl Future<Output = ZammResult<LlmCall>>,
+) -> ZammResult<LlmCall> {
+    let request_id = aborted.id.uuid;
+    match run_abortable(active_chats, request_id, request).await {
+        Ok(result) => result,
+        Err(record_aborted) => {
+            if record_aborted {
+                let previous_call_id =
+                    get_previous_call_id(zamm_db, aborted.conversation_id.as_ref())?;
+                let Prompt::Chat(ChatPrompt { messages }) = aborted.request.prompt;
+                let prompt = Prompt::Chat(ChatPrompt {
+                    messages: store_images(blob_store, messages)?,
+                });
+                record_llm_call(
+                    &mut *zamm_db.connection()?,
+                    &LlmCall {
+                        timestamp: chrono::Utc::now().naive_utc(),
+                        request: Request {
+                            prompt,
+                            ..aborted.request
+                        },
+                        previous_call_id,
+                        ..aborted
+                    },
+                )?;
+            }
+            Err(Error::Cancelled { id: request_id })
+        }
+    }
+}
+
+/// Runs a request that only changes an existing call, so that `cancel_chat` can
+/// abort it. Nothing new gets recorded when it is aborted.
+pub async fn cancellable_update(
+    active_chats: &ZammActiveChats,
+    request_id: Uuid,
+    request: impl Future<Output = ZammResult<LlmCall>>,
+) -> ZammResult<LlmCall> {
+    run_abortable(active_chats, request_id, request)
+        .await
+        .unwrap_or(Err(Error::Cancelled { id: request_id }))
+}
+
+async fn cancel_chat_helper(
+    active_chats: &ZammActiveChats,
+    request_id: Uuid,
+    record_aborted: Option<bool>,
+) -> ZammResult<()> {
+    let mut chats = active_chats.0.lock().await;
+    let active_chat = chats
+        .get_mut(&request_id)
+        .ok_or(Error::NoActiveChat { id: request_id })?;
+    active_chat.record_aborted = record_aborted.unwrap_or(false);
+    active_chat.abort_handle.abort();
+    Ok(())
+}
+
+/// Stops a chat request that is still waiting on its provider, optionally keeping a
+/// record of it in the history.
+#[tauri::command(async)]
+#[specta]
+pub async fn cancel_chat(
+    active_chats: State<'_, ZammActiveChats>,
+    request_id: Uuid,
+    record_aborted: Option<bool>,
+) -> ZammResult<()> {
+    cancel_chat_helper(&active_chats, request_id, record_aborted).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::get_llm_call;
+    use crate::models::llm_calls::ChatImage;
+    use crate::schema::llm_calls;
+    use crate::test_helpers::{setup_blob_store, setup_zamm_db};
+    use diesel::prelude::*;
+    use std::collections::HashMap;
+    use tokio::sync::Mutex;
+
+    fn sample_aborted_call() -> LlmCall {
+        aborted_call(
+            Uuid::new_v4(),
+            &Service::OpenAI,
+            "gpt-4",
+            None,
+            &[ChatMessage::Human {
+                text: "Write me a very long essay".to_string(),
+                images: vec![],
+            }],
+            None,
+            None,
+        )
+    }
+
+    async fn cancel_pending_chat(
+        db: &ZammDatabase,
+        aborted: LlmCall,
+        record_aborted: Option<bool>,
+    ) -> ZammResult<LlmCall> {
+        let active_chats = ZammActiveChats(Mutex::new(HashMap::new()));
+        let request_id = aborted.id.uuid;
+        let (result, cancel_result) = tokio::join!(
+            cancellable_chat(
+                &active_chats,
+                db,
+                &setup_blob_store(),
+                aborted,
+                futures::future::pending()
+            ),
+            async {
+                tokio::task::yield_now().await;
+                cancel_chat_helper(&active_chats, request_id, record_aborted).await
+            }
+        );
+        cancel_result.unwrap();
+        assert!(active_chats.0.lock().await.is_empty());
+        result
+    }
+
+    async fn count_llm_calls(db: &ZammDatabase) -> i64 {
+        let mut conn = db.connection().unwrap();
+        llm_calls::table.count().get_result(&mut *conn).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cancel_without_recording() {
+        let db = setup_zamm_db();
+        let aborted = sample_aborted_call();
+        let request_id = aborted.id.uuid;
+
+        let result = cancel_pending_chat(&db, aborted, None).await;
+        assert!(matches!(result, Err(Error::Cancelled { id }) if id == request_id));
+        assert_eq!(count_llm_calls(&db).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_recorded_as_aborted() {
+        let db = setup_zamm_db();
+        let aborted = sample_aborted_call();
+        let aborted_id = aborted.id.clone();
+
+        let result = cancel_pending_chat(&db, aborted, Some(true)).await;
+        assert!(matches!(result, Err(Error::Cancelled { .. })));
+        let stored = get_llm_call(&db, &aborted_id).await;
+        assert_eq!(stored.response.finish_reason, Some(FinishReason::Aborted));
+        assert_eq!(stored.llm.requested, "gpt-4");
+    }
+
+    #[tokio::test]
+    async fn test_aborted_images_stored_by_hash() {
+        let db = setup_zamm_db();
+        let aborted = aborted_call(
+            Uuid::new_v4(),
+            &Service::OpenAI,
+            "gpt-4",
+            None,
+            &[ChatMessage::Human {
+                text: "What's this?".to_string(),
+                images: vec![ChatImage::Base64 {
+                    media_type: "image/png".to_string(),
+                    data: "aGVsbG8=".to_string(),
+                }],
+            }],
+            None,
+            None,
+        );
+        let aborted_id = aborted.id.clone();
+
+        cancel_pending_chat(&db, aborted, Some(true))
+            .await
+            .unwrap_err();
+        let stored = get_llm_call(&db, &aborted_id).await;
+        let Prompt::Chat(ChatPrompt { messages }) = stored.request.prompt;
+        let ChatMessage::Human { images, .. } = &messages[0] else {
+            panic!("Unexpected message type");
+        };
+        let ChatImage::Blob { hash, .. } = &images[0] else {
+            panic!("Image not moved into blob store");
+        };
+        assert_eq!(setup_blob_store().get(hash).unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_update() {
+        let active_chats = ZammActiveChats(Mutex::new(HashMap::new()));
+        let request_id = Uuid::new_v4();
+        let (result, cancel_result) = tokio::join!(
+            cancellable_update(&active_chats, request_id, futures::future::pending()),
+            async {
+                tokio::task::yield_now().await;
+                cancel_chat_helper(&active_chats, request_id, Some(true)).await
+            }
+        );
+        cancel_result.unwrap();
+        assert!(matches!(result, Err(Error::Cancelled { id }) if id == request_id));
+        assert!(active_chats.0.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unknown_request() {
+        let active_chats = ZammActiveChats(Mutex::new(HashMap::new()));
+        let result = cancel_chat_helper(&active_chats, Uuid::new_v4(), None).await;
+        assert!(matches!(result, Err(Error::NoActiveChat { .. })));
+    }
+}
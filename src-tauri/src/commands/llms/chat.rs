@@ -1,61 +1,229 @@
+use crate::commands::conversations::{get_latest_call_id, touch_conversation};
 use crate::commands::errors::ZammResult;
+use crate::commands::llms::anthropic::chat_anthropic;
+use crate::commands::llms::budget::{check_call_budgets, CallEstimate};
+use crate::commands::llms::cancel::{aborted_call, cancellable_chat};
+use crate::commands::llms::images::{load_images, store_images};
+use crate::commands::llms::pricing::compute_cost;
+use crate::commands::llms::retry::llm_http_client;
+use crate::commands::llms::tools::{get_tools, openai_tools, run_tool_call, LocalTool};
+use crate::commands::preferences::{get_preferences_helper, Preferences};
 use crate::commands::Error;
 use crate::models::llm_calls::{
-    ChatMessage, ChatPrompt, EntityId, Llm, LlmCall, Prompt, Request, Response,
-    TokenMetadata,
+    ChatMessage, ChatParameters, ChatPrompt, CompletionChoice, CompletionChoices,
+    EntityId, Llm, LlmCall, Prompt, Request, Response, ResponseFormat, TokenMetadata,
 };
 use crate::schema::llm_calls;
-use crate::setup::api_keys::Service;
-use crate::{ZammApiKeys, ZammDatabase};
+use crate::setup::api_keys::{ApiKeys, Service};
+use crate::setup::blobs::BlobStore;
+use crate::setup::get_blob_store;
+use crate::{ZammActiveChats, ZammApiKeys, ZammDatabase};
+use anyhow::anyhow;
 use async_openai::config::OpenAIConfig;
 use async_openai::types::{
-    ChatCompletionRequestMessage, CreateChatCompletionRequestArgs,
+    ChatCompletionRequestMessage, ChatCompletionResponseFormat,
+    ChatCompletionResponseFormatType, CreateChatCompletionRequest,
+    CreateChatCompletionRequestArgs,
 };
-use diesel::RunQueryDsl;
+use diesel::prelude::*;
 use specta::specta;
+use std::time::Duration;
 use tauri::State;
 use uuid::Uuid;
 
-async fn chat_helper(
-    zamm_api_keys: &ZammApiKeys,
-    zamm_db: &ZammDatabase,
-    provider: Service,
-    llm: String,
-    temperature: Option<f32>,
-    prompt: Vec<ChatMessage>,
-    http_client: reqwest_middleware::ClientWithMiddleware,
-) -> ZammResult<LlmCall> {
-    let api_keys = zamm_api_keys.0.lock().await;
-    if api_keys.openai.is_none() {
-        return Err(Error::MissingApiKey {
-            service: Service::OpenAI,
-        });
-    }
+/// The parts of an LLM call that come back from the provider.
+pub struct LlmCompletion {
+    pub model: String,
+    /// Always contains at least one choice.
+    pub choices: Vec<CompletionChoice>,
+    pub tokens: TokenMetadata,
+}
+
+/// Splits out the first choice as the main completion, keeping the full list of
+/// choices only if there are alternatives to it.
+pub fn response_from_choices(choices: Vec<CompletionChoice>) -> ZammResult<Response> {
+    let first_choice =
+        choices
+            .first()
+            .cloned()
+            .ok_or(Error::UnexpectedOpenAiResponse {
+                reason: "Zero choices".to_owned(),
+            })?;
+    let choices = if choices.len() > 1 {
+        CompletionChoices(choices)
+    } else {
+        CompletionChoices::default()
+    };
+    Ok(Response {
+        completion: first_choice.completion,
+        finish_reason: first_choice.finish_reason,
+        choices,
+    })
+}
 
-    let db = &mut zamm_db.0.lock().await;
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+/// How many times the model may ask for tools to be run before we give up on it
+/// ever replying with text.
+const MAX_TOOL_ROUNDS: usize = 10;
 
-    let config = match provider {
+pub fn get_openai_config(
+    api_keys: &ApiKeys,
+    preferences: &Preferences,
+    provider: &Service,
+) -> ZammResult<OpenAIConfig> {
+    match provider {
         Service::OpenAI => {
             let openai_api_key =
                 api_keys.openai.as_ref().ok_or(Error::MissingApiKey {
                     service: Service::OpenAI,
                 })?;
-            OpenAIConfig::new().with_api_key(openai_api_key)
+            Ok(OpenAIConfig::new().with_api_key(openai_api_key))
         }
-    };
+        Service::Ollama => {
+            let base_url = preferences
+                .ollama_base_url
+                .as_deref()
+                .unwrap_or(DEFAULT_OLLAMA_BASE_URL);
+            // explicitly blank out the key so that OpenAIConfig doesn't pick up
+            // OPENAI_API_KEY from the environment and send it to a local server
+            Ok(OpenAIConfig::new().with_api_base(base_url).with_api_key(""))
+        }
+        _ => Err(Error::UnsupportedFeature {
+            service: provider.clone(),
+            feature: "OpenAI-compatible API".to_string(),
+        }),
+    }
+}
 
-    let requested_model = llm;
-    let requested_temperature = temperature.unwrap_or(1.0);
+/// Looks up the call that a new call in the given conversation should follow on
+/// from, before any request is made.
+pub fn get_previous_call_id(
+    zamm_db: &ZammDatabase,
+    conversation_id: Option<&EntityId>,
+) -> ZammResult<Option<EntityId>> {
+    match conversation_id {
+        Some(conversation_id) => {
+            get_latest_call_id(&mut *zamm_db.connection()?, conversation_id)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Stores an LLM call, bumping the conversation it belongs to (if any) to the top.
+pub fn record_llm_call(
+    conn: &mut SqliteConnection,
+    llm_call: &LlmCall,
+) -> ZammResult<()> {
+    conn.transaction(|conn| {
+        diesel::insert_into(llm_calls::table)
+            .values(llm_call.as_sql_row())
+            .execute(conn)?;
+        if let Some(conversation_id) = &llm_call.conversation_id {
+            touch_conversation(conn, conversation_id, &llm_call.timestamp)?;
+        }
+        Ok(())
+    })
+}
+
+/// Stores an LLM call if the database is available. By then the call has already
+/// been paid for, so a failure to store it gets reported as a warning on the call
+/// rather than as an error.
+pub fn persist_llm_call(zamm_db: &ZammDatabase, llm_call: LlmCall) -> LlmCall {
+    let result = zamm_db
+        .connection()
+        .and_then(|mut conn| record_llm_call(&mut conn, &llm_call));
+    match result {
+        Ok(()) => llm_call,
+        Err(e) => {
+            // a missing database already has the reason for it recorded
+            if !matches!(e, Error::MissingDatabase {}) {
+                zamm_db.record_error(&e);
+            }
+            let not_saved = format!("This call was not saved to the database: {e}");
+            let warning = match llm_call.warning {
+                Some(warning) => format!("{warning}\n{not_saved}"),
+                None => not_saved,
+            };
+            LlmCall {
+                warning: Some(warning),
+                ..llm_call
+            }
+        }
+    }
+}
+
+/// Builds an OpenAI chat completion request, passing along any optional
+/// parameters that were set.
+pub fn openai_request(
+    model: &str,
+    temperature: f32,
+    prompt: &[ChatMessage],
+    parameters: &ChatParameters,
+    tools: &[&dyn LocalTool],
+) -> ZammResult<CreateChatCompletionRequest> {
+    let messages = prompt
+        .iter()
+        .cloned()
+        .map(|m| m.try_into())
+        .collect::<ZammResult<Vec<ChatCompletionRequestMessage>>>()?;
+    let mut args = CreateChatCompletionRequestArgs::default();
+    args.model(model)
+        .temperature(temperature)
+        .messages(messages);
+    if let Some(max_tokens) = parameters.max_tokens {
+        args.max_tokens(u16::try_from(max_tokens).map_err(|e| anyhow!(e))?);
+    }
+    if let Some(top_p) = parameters.top_p {
+        args.top_p(top_p);
+    }
+    if let Some(presence_penalty) = parameters.presence_penalty {
+        args.presence_penalty(presence_penalty);
+    }
+    if let Some(frequency_penalty) = parameters.frequency_penalty {
+        args.frequency_penalty(frequency_penalty);
+    }
+    if let Some(stop) = &parameters.stop {
+        args.stop(stop.0.clone());
+    }
+    if let Some(seed) = parameters.seed {
+        args.seed(seed);
+    }
+    if let Some(n) = parameters.n {
+        args.n(u8::try_from(n).map_err(|e| anyhow!(e))?);
+    }
+    if let Some(response_format) = parameters.response_format {
+        let format_type = match response_format {
+            ResponseFormat::Text => ChatCompletionResponseFormatType::Text,
+            ResponseFormat::JsonObject => ChatCompletionResponseFormatType::JsonObject,
+        };
+        args.response_format(ChatCompletionResponseFormat {
+            r#type: format_type,
+        });
+    }
+    if !tools.is_empty() {
+        args.tools(openai_tools(tools));
+    }
+    Ok(args.build()?)
+}
 
-    let openai_client =
-        async_openai::Client::with_config(config).with_http_client(http_client);
-    let messages: Vec<ChatCompletionRequestMessage> =
-        prompt.clone().into_iter().map(|m| m.into()).collect();
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(&requested_model)
-        .temperature(requested_temperature)
-        .messages(messages)
-        .build()?;
+async fn chat_openai(
+    config: OpenAIConfig,
+    http_client: reqwest_middleware::ClientWithMiddleware,
+    model: &str,
+    temperature: f32,
+    prompt: &[ChatMessage],
+    parameters: &ChatParameters,
+    tools: &[&dyn LocalTool],
+) -> ZammResult<LlmCompletion> {
+    // retries are left to the HTTP client's middleware, which follows the user's
+    // preferences and logs each attempt
+    let no_retries = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+    let openai_client = async_openai::Client::with_config(config)
+        .with_http_client(http_client)
+        .with_backoff(no_retries);
+    let request = openai_request(model, temperature, prompt, parameters, tools)?;
     let response = openai_client.chat().create(&request).await?;
 
     let token_metadata = TokenMetadata {
@@ -72,83 +240,236 @@ async fn chat_helper(
             .as_ref()
             .map(|usage| usage.total_tokens as i32),
     };
-    let sole_choice = response
+    let choices = response
         .choices
-        .first()
-        .ok_or(Error::UnexpectedOpenAiResponse {
-            reason: "Zero choices".to_owned(),
-        })?
-        .message
-        .to_owned();
-    let llm_call = LlmCall {
-        id: EntityId {
-            uuid: Uuid::new_v4(),
-        },
-        timestamp: chrono::Utc::now().naive_utc(),
-        llm: Llm {
-            provider: Service::OpenAI,
-            name: response.model.clone(),
-            requested: requested_model.to_owned(),
-        },
-        request: Request {
-            temperature: requested_temperature,
-            prompt: Prompt::Chat(ChatPrompt { messages: prompt }),
-        },
-        response: Response {
-            completion: sole_choice.try_into()?,
-        },
+        .into_iter()
+        .map(|choice| {
+            Ok(CompletionChoice {
+                completion: choice.message.try_into()?,
+                finish_reason: choice.finish_reason.map(|reason| reason.into()),
+            })
+        })
+        .collect::<ZammResult<Vec<CompletionChoice>>>()?;
+    Ok(LlmCompletion {
+        model: response.model,
+        choices,
         tokens: token_metadata,
-    };
+    })
+}
 
-    if let Some(conn) = db.as_mut() {
-        diesel::insert_into(llm_calls::table)
-            .values(llm_call.as_sql_row())
-            .execute(conn)?;
-    } // todo: warn users if DB write unsuccessful
+/// Sends a single request to the provider, without recording anything about it.
+#[allow(clippy::too_many_arguments)]
+pub async fn request_completion(
+    api_keys: &ApiKeys,
+    blob_store: &BlobStore,
+    preferences: &Preferences,
+    provider: &Service,
+    model: &str,
+    temperature: f32,
+    prompt: &[ChatMessage],
+    parameters: &ChatParameters,
+    tools: &[&dyn LocalTool],
+    http_client: reqwest_middleware::ClientWithMiddleware,
+) -> ZammResult<LlmCompletion> {
+    let api_prompt = load_images(blob_store, prompt)?;
+    match provider {
+        Service::OpenAI | Service::Ollama => {
+            let config = get_openai_config(api_keys, preferences, provider)?;
+            chat_openai(
+                config,
+                http_client,
+                model,
+                temperature,
+                &api_prompt,
+                parameters,
+                tools,
+            )
+            .await
+        }
+        Service::Anthropic => {
+            let anthropic_api_key =
+                api_keys.anthropic.as_ref().ok_or(Error::MissingApiKey {
+                    service: Service::Anthropic,
+                })?;
+            chat_anthropic(
+                &http_client,
+                anthropic_api_key,
+                model,
+                temperature,
+                &api_prompt,
+                parameters,
+            )
+            .await
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn chat_helper(
+    zamm_api_keys: &ZammApiKeys,
+    zamm_db: &ZammDatabase,
+    blob_store: &BlobStore,
+    preferences: &Preferences,
+    provider: Service,
+    llm: String,
+    temperature: Option<f32>,
+    prompt: Vec<ChatMessage>,
+    parameters: Option<ChatParameters>,
+    conversation_id: Option<Uuid>,
+    ignore_budget: Option<bool>,
+    http_client: reqwest_middleware::ClientWithMiddleware,
+) -> ZammResult<LlmCall> {
+    // neither the keys nor a database connection are held on to while waiting on
+    // the LLM, so that other commands can use them in the meantime
+    let api_keys = zamm_api_keys.0.lock().await.clone();
+    let conversation_id = conversation_id.map(|uuid| EntityId { uuid });
+    let mut previous_call_id = get_previous_call_id(zamm_db, conversation_id.as_ref())?;
+    let requested_model = llm;
+    let requested_temperature = temperature.unwrap_or(1.0);
+    let parameters = parameters.unwrap_or_default();
+    let tools = get_tools(parameters.tools.as_ref())?;
+    // only image hashes get recorded, but the full image data gets sent
+    let mut prompt = store_images(blob_store, prompt)?;
+
+    // every round of tool calls is recorded as its own LLM call, each following on
+    // from the last
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let budget_warning = if ignore_budget.unwrap_or(false) {
+            None
+        } else {
+            let estimate = CallEstimate::new(
+                preferences,
+                &provider,
+                &requested_model,
+                &prompt,
+                &parameters,
+            );
+            check_call_budgets(zamm_db, preferences, &provider, &estimate)?
+        };
+        let llm_completion = request_completion(
+            &api_keys,
+            blob_store,
+            preferences,
+            &provider,
+            &requested_model,
+            requested_temperature,
+            &prompt,
+            &parameters,
+            &tools,
+            http_client.clone(),
+        )
+        .await?;
+
+        let llm = Llm {
+            provider: provider.clone(),
+            name: llm_completion.model,
+            requested: requested_model.clone(),
+        };
+        let cost = compute_cost(preferences, &llm, &llm_completion.tokens);
+        let llm_call = LlmCall {
+            id: EntityId {
+                uuid: Uuid::new_v4(),
+            },
+            timestamp: chrono::Utc::now().naive_utc(),
+            llm,
+            request: Request {
+                temperature: requested_temperature,
+                prompt: Prompt::Chat(ChatPrompt {
+                    messages: prompt.clone(),
+                }),
+                parameters: parameters.clone(),
+            },
+            response: response_from_choices(llm_completion.choices)?,
+            tokens: llm_completion.tokens,
+            cost,
+            conversation_id: conversation_id.clone(),
+            previous_call_id: previous_call_id.clone(),
+            warning: budget_warning,
+        };
+        let llm_call = persist_llm_call(zamm_db, llm_call);
+
+        let tool_calls = match &llm_call.response.completion {
+            ChatMessage::AIToolCalls { tool_calls } if !tools.is_empty() => {
+                tool_calls.clone()
+            }
+            _ => return Ok(llm_call),
+        };
+        prompt.push(llm_call.response.completion);
+        prompt.extend(
+            tool_calls
+                .iter()
+                .map(|tool_call| run_tool_call(&tools, tool_call)),
+        );
+        previous_call_id = Some(llm_call.id);
+    }
 
-    Ok(llm_call)
+    Err(Error::TooManyToolRounds {
+        rounds: MAX_TOOL_ROUNDS,
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tauri::command(async)]
 #[specta]
 pub async fn chat(
+    app_handle: tauri::AppHandle,
     api_keys: State<'_, ZammApiKeys>,
     database: State<'_, ZammDatabase>,
+    active_chats: State<'_, ZammActiveChats>,
     provider: Service,
     llm: String,
     temperature: Option<f32>,
     prompt: Vec<ChatMessage>,
+    parameters: Option<ChatParameters>,
+    conversation_id: Option<Uuid>,
+    ignore_budget: Option<bool>,
+    request_id: Option<Uuid>,
 ) -> ZammResult<LlmCall> {
-    let http_client = reqwest::ClientBuilder::new().build()?;
-    let client_with_middleware =
-        reqwest_middleware::ClientBuilder::new(http_client).build();
-    chat_helper(
+    let preferences =
+        get_preferences_helper(&app_handle.path_resolver().app_config_dir());
+    let client_with_middleware = llm_http_client(&preferences)?;
+    let blob_store = get_blob_store(&database);
+    let aborted = aborted_call(
+        request_id.unwrap_or_else(Uuid::new_v4),
+        &provider,
+        &llm,
+        temperature,
+        &prompt,
+        parameters.as_ref(),
+        conversation_id,
+    );
+    let request = chat_helper(
         &api_keys,
         &database,
+        &blob_store,
+        &preferences,
         provider,
         llm,
         temperature,
         prompt,
+        parameters,
+        conversation_id,
+        ignore_budget,
         client_with_middleware,
-    )
-    .await
+    );
+    cancellable_chat(&active_chats, &database, &blob_store, aborted, request).await
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
-    use crate::models::llm_calls::{ChatMessage, LlmCallRow};
+    use crate::commands::conversations::tests::insert_conversation;
+    use crate::models::llm_calls::{ChatMessage, FinishReason, LlmCallRow};
     use crate::sample_call::SampleCall;
-    use crate::setup::api_keys::ApiKeys;
-    use crate::setup::db::MIGRATIONS;
-    use diesel::prelude::*;
-    use diesel_migrations::MigrationHarness;
-    use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+    use crate::test_helpers::TestDatabase;
+    pub use crate::test_helpers::{setup_blob_store, setup_zamm_db};
+    use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
     use rvcr::{VCRMiddleware, VCRMode};
     use serde::{Deserialize, Serialize};
     use std::env;
     use std::fs;
     use std::path::PathBuf;
+    use std::sync::Arc;
     use tokio::sync::Mutex;
     use vcr_cassette::Headers;
 
@@ -168,20 +489,9 @@ mod tests {
             .collect();
     }
 
-    fn setup_database() -> SqliteConnection {
-        let mut conn = SqliteConnection::establish(":memory:").unwrap();
-        conn.run_pending_migrations(MIGRATIONS).unwrap();
-        conn
-    }
-
-    pub fn setup_zamm_db() -> ZammDatabase {
-        ZammDatabase(Mutex::new(Some(setup_database())))
-    }
-
-    async fn get_llm_call(db: &ZammDatabase, call_id: &EntityId) -> LlmCall {
+    pub async fn get_llm_call(db: &ZammDatabase, call_id: &EntityId) -> LlmCall {
         use crate::schema::llm_calls::dsl::*;
-        let mut conn_mutex = db.0.lock().await;
-        let conn = conn_mutex.as_mut().unwrap();
+        let conn = &mut *db.connection().unwrap();
         llm_calls
             .filter(id.eq(call_id))
             .first::<LlmCallRow>(conn)
@@ -190,37 +500,69 @@ mod tests {
     }
 
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    struct ChatRequest {
-        provider: Service,
-        llm: String,
-        temperature: Option<f32>,
-        prompt: Vec<ChatMessage>,
+    pub struct ChatRequest {
+        pub provider: Service,
+        pub llm: String,
+        pub temperature: Option<f32>,
+        pub prompt: Vec<ChatMessage>,
+        pub parameters: Option<ChatParameters>,
+        pub conversation_id: Option<Uuid>,
+        pub ignore_budget: Option<bool>,
+        pub request_id: Option<Uuid>,
     }
 
-    fn parse_request(request_str: &str) -> ChatRequest {
+    pub fn parse_request(request_str: &str) -> ChatRequest {
         serde_json::from_str(request_str).unwrap()
     }
 
-    fn parse_response(response_str: &str) -> LlmCall {
+    pub fn parse_response(response_str: &str) -> LlmCall {
         serde_json::from_str(response_str).unwrap()
     }
 
-    fn read_sample(filename: &str) -> SampleCall {
+    pub fn read_sample(filename: &str) -> SampleCall {
         let sample_str = fs::read_to_string(filename)
             .unwrap_or_else(|_| panic!("No file found at {filename}"));
         serde_yaml::from_str(&sample_str).unwrap()
     }
 
-    async fn test_llm_api_call(recording_path: &str, sample_path: &str) {
+    /// Reads the LLM call from a sample file, giving it a fresh ID and the given
+    /// timestamp so that several copies of the same sample can be stored at once.
+    pub fn read_sample_llm_call(sample_path: &str, timestamp: &str) -> LlmCall {
+        let sample = read_sample(sample_path);
+        LlmCall {
+            id: EntityId {
+                uuid: Uuid::new_v4(),
+            },
+            timestamp: chrono::NaiveDateTime::parse_from_str(
+                timestamp,
+                "%Y-%m-%dT%H:%M:%S",
+            )
+            .unwrap(),
+            ..parse_response(&sample.response.message)
+        }
+    }
+
+    pub async fn insert_llm_call(db: &ZammDatabase, llm_call: &LlmCall) {
+        let mut conn = db.connection().unwrap();
+        record_llm_call(&mut *conn, llm_call).unwrap();
+    }
+
+    /// Returns API keys and a middleware that will either replay the recording at
+    /// the given path, or make a fresh recording there if none exists yet.
+    pub fn setup_vcr_middleware(recording_path: &str) -> (ZammApiKeys, VCRMiddleware) {
         let recording_path = PathBuf::from(recording_path);
         let is_recording = !recording_path.exists();
         let api_keys = if is_recording {
             ZammApiKeys(Mutex::new(ApiKeys {
                 openai: env::var("OPENAI_API_KEY").ok(),
+                anthropic: env::var("ANTHROPIC_API_KEY").ok(),
+                ..ApiKeys::default()
             }))
         } else {
             ZammApiKeys(Mutex::new(ApiKeys {
                 openai: Some("dummy".to_string()),
+                anthropic: Some("dummy".to_string()),
+                ..ApiKeys::default()
             }))
         };
 
@@ -233,36 +575,33 @@ mod tests {
             .unwrap()
             .with_mode(vcr_mode)
             .with_modify_request(|req| {
-                req.headers = censor_headers(&req.headers, &["authorization"]);
+                req.headers =
+                    censor_headers(&req.headers, &["authorization", "x-api-key"]);
             })
             .with_modify_response(|resp| {
                 resp.headers = censor_headers(&resp.headers, &["openai-organization"]);
             });
 
+        (api_keys, middleware)
+    }
+
+    /// Returns API keys and an HTTP client that will either replay the recording at
+    /// the given path, or make a fresh recording there if none exists yet.
+    pub fn setup_vcr(recording_path: &str) -> (ZammApiKeys, ClientWithMiddleware) {
+        let (api_keys, middleware) = setup_vcr_middleware(recording_path);
         let vcr_client: ClientWithMiddleware =
             ClientBuilder::new(reqwest::Client::new())
                 .with(middleware)
                 .build();
+        (api_keys, vcr_client)
+    }
 
-        let db = setup_zamm_db();
-        // end dependencies setup
-
-        let sample = read_sample(sample_path);
-        assert_eq!(sample.request.len(), 2);
-        assert_eq!(sample.request[0], "chat");
-
-        let request = parse_request(&sample.request[1]);
-
-        let result = chat_helper(
-            &api_keys,
-            &db,
-            request.provider,
-            request.llm,
-            request.temperature,
-            request.prompt,
-            vcr_client,
-        )
-        .await;
+    /// Checks the parts of an LLM call that should match the sample exactly.
+    pub async fn check_llm_call_result(
+        db: &ZammDatabase,
+        sample: &SampleCall,
+        result: ZammResult<LlmCall>,
+    ) {
         assert!(result.is_ok(), "Error: {:?}", result.err());
         let ok_result = result.unwrap();
 
@@ -280,12 +619,17 @@ mod tests {
         assert_eq!(actual_json, expected_json);
 
         // check that it made it into the database
-        let stored_llm_call = get_llm_call(&db, &ok_result.id).await;
+        let stored_llm_call = get_llm_call(db, &ok_result.id).await;
         assert_eq!(stored_llm_call.request.prompt, ok_result.request.prompt);
+        assert_eq!(
+            stored_llm_call.request.parameters,
+            ok_result.request.parameters
+        );
         assert_eq!(
             stored_llm_call.response.completion,
             ok_result.response.completion
         );
+        assert_eq!(stored_llm_call.response.choices, ok_result.response.choices);
 
         // do a sanity check that everything is non-empty
         let prompt = match ok_result.request.prompt {
@@ -298,6 +642,35 @@ mod tests {
         }
     }
 
+    async fn test_llm_api_call(recording_path: &str, sample_path: &str) {
+        let (api_keys, vcr_client) = setup_vcr(recording_path);
+        let db = setup_zamm_db();
+        // end dependencies setup
+
+        let sample = read_sample(sample_path);
+        assert_eq!(sample.request.len(), 2);
+        assert_eq!(sample.request[0], "chat");
+
+        let request = parse_request(&sample.request[1]);
+
+        let result = chat_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            request.parameters,
+            request.conversation_id,
+            request.ignore_budget,
+            vcr_client,
+        )
+        .await;
+        check_llm_call_result(&db, &sample, result).await;
+    }
+
     #[tokio::test]
     async fn test_start_conversation() {
         test_llm_api_call(
@@ -315,4 +688,362 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test]
+    async fn test_json_mode_with_parameters() {
+        test_llm_api_call(
+            "api/sample-call-requests/json-mode.json",
+            "api/sample-calls/chat-json-mode.yaml",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_multiple_choices() {
+        test_llm_api_call(
+            "api/sample-call-requests/multiple-choices.json",
+            "api/sample-calls/chat-multiple-choices.yaml",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_start_conversation() {
+        test_llm_api_call(
+            "api/sample-call-requests/anthropic-start-conversation.json",
+            "api/sample-calls/chat-anthropic-start-conversation.yaml",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_ollama_start_conversation() {
+        test_llm_api_call(
+            "api/sample-call-requests/ollama-start-conversation.json",
+            "api/sample-calls/chat-ollama-start-conversation.yaml",
+        )
+        .await;
+    }
+
+    /// Fails the request if the API keys or the database are still locked while it
+    /// is in flight.
+    struct AssertUnlocked {
+        api_keys: Arc<ZammApiKeys>,
+        db: Arc<TestDatabase>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for AssertUnlocked {
+        async fn handle(
+            &self,
+            req: reqwest::Request,
+            extensions: &mut task_local_extensions::Extensions,
+            next: Next<'_>,
+        ) -> reqwest_middleware::Result<reqwest::Response> {
+            assert!(self.api_keys.0.try_lock().is_ok(), "API keys still locked");
+            let pool = self.db.0.read().unwrap().pool.clone().unwrap();
+            assert!(pool.try_get().is_some(), "Database connection still held");
+            next.run(req, extensions).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_locks_released_during_request() {
+        let (api_keys, vcr_middleware) =
+            setup_vcr_middleware("api/sample-call-requests/start-conversation.json");
+        let api_keys = Arc::new(api_keys);
+        let db = Arc::new(setup_zamm_db());
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(AssertUnlocked {
+                api_keys: api_keys.clone(),
+                db: db.clone(),
+            })
+            .with(vcr_middleware)
+            .build();
+        let sample = read_sample("api/sample-calls/chat-start-conversation.yaml");
+        let request = parse_request(&sample.request[1]);
+
+        let result = chat_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            request.parameters,
+            request.conversation_id,
+            request.ignore_budget,
+            client,
+        )
+        .await;
+        check_llm_call_result(&db, &sample, result).await;
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_until_text_reply() {
+        let (api_keys, vcr_client) =
+            setup_vcr("api/sample-call-requests/tool-calls.json");
+        let db = setup_zamm_db();
+        let sample = read_sample("api/sample-calls/chat-tool-calls.yaml");
+        let request = parse_request(&sample.request[1]);
+
+        let final_call = chat_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            request.parameters,
+            request.conversation_id,
+            request.ignore_budget,
+            vcr_client,
+        )
+        .await
+        .unwrap();
+
+        // the step where the model asked for the tool gets recorded too
+        let tool_call_id = final_call.previous_call_id.clone().unwrap();
+        let tool_call_step = get_llm_call(&db, &tool_call_id).await;
+        assert_eq!(
+            tool_call_step.response.finish_reason,
+            Some(FinishReason::ToolCalls)
+        );
+        assert!(matches!(
+            tool_call_step.response.completion,
+            ChatMessage::AIToolCalls { .. }
+        ));
+        assert_eq!(tool_call_step.previous_call_id, None);
+
+        let expected_llm_call = parse_response(&sample.response.message);
+        let result = Ok(LlmCall {
+            previous_call_id: expected_llm_call.previous_call_id,
+            ..final_call
+        });
+        check_llm_call_result(&db, &sample, result).await;
+    }
+
+    #[test]
+    fn test_openai_request_with_images() {
+        use crate::models::llm_calls::ChatImage;
+
+        let message = ChatMessage::Human {
+            text: "What is this?".to_string(),
+            images: vec![ChatImage::Base64 {
+                media_type: "image/png".to_string(),
+                data: "iVBORw0KGgo=".to_string(),
+            }],
+        };
+        let request = openai_request(
+            "gpt-4-vision-preview",
+            1.0,
+            &[message.clone()],
+            &ChatParameters::default(),
+            &[],
+        )
+        .unwrap();
+
+        let request_json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            request_json["messages"][0]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+        let round_tripped: ChatMessage =
+            request.messages[0].clone().try_into().unwrap();
+        assert_eq!(round_tripped, message);
+    }
+
+    async fn chat_in_conversation(
+        db: &ZammDatabase,
+        recording_path: &str,
+        sample_path: &str,
+        conversation_id: &EntityId,
+    ) -> LlmCall {
+        let (api_keys, vcr_client) = setup_vcr(recording_path);
+        let request = parse_request(&read_sample(sample_path).request[1]);
+        chat_helper(
+            &api_keys,
+            db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            None,
+            Some(conversation_id.uuid),
+            None,
+            vcr_client,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_calls_linked_within_conversation() {
+        let db = setup_zamm_db();
+        let conversation =
+            insert_conversation(&db, "Greetings", "2024-03-01T10:00:00").await;
+
+        let first_call = chat_in_conversation(
+            &db,
+            "api/sample-call-requests/start-conversation.json",
+            "api/sample-calls/chat-start-conversation.yaml",
+            &conversation.id,
+        )
+        .await;
+        assert_eq!(first_call.conversation_id, Some(conversation.id.clone()));
+        assert_eq!(first_call.previous_call_id, None);
+
+        let second_call = chat_in_conversation(
+            &db,
+            "api/sample-call-requests/continue-conversation.json",
+            "api/sample-calls/chat-continue-conversation.yaml",
+            &conversation.id,
+        )
+        .await;
+        assert_eq!(second_call.previous_call_id, Some(first_call.id.clone()));
+
+        let stored_call = get_llm_call(&db, &second_call.id).await;
+        assert_eq!(stored_call.conversation_id, Some(conversation.id.clone()));
+        assert_eq!(stored_call.previous_call_id, Some(first_call.id));
+
+        // the conversation should now show up as recently updated
+        let mut conn = db.connection().unwrap();
+        let updated_at: chrono::NaiveDateTime = crate::schema::conversations::table
+            .find(&conversation.id)
+            .select(crate::schema::conversations::updated_at)
+            .first(&mut *conn)
+            .unwrap();
+        assert_eq!(updated_at, second_call.timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_chat_in_nonexistent_conversation() {
+        let (api_keys, vcr_client) =
+            setup_vcr("api/sample-call-requests/start-conversation.json");
+        let db = setup_zamm_db();
+        let sample = read_sample("api/sample-calls/chat-start-conversation.yaml");
+        let request = parse_request(&sample.request[1]);
+
+        let result = chat_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            None,
+            Some(Uuid::new_v4()),
+            None,
+            vcr_client,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    async fn chat_without_saving(db: &ZammDatabase) -> LlmCall {
+        let (api_keys, vcr_client) =
+            setup_vcr("api/sample-call-requests/start-conversation.json");
+        let sample = read_sample("api/sample-calls/chat-start-conversation.yaml");
+        let request = parse_request(&sample.request[1]);
+
+        chat_helper(
+            &api_keys,
+            db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            None,
+            None,
+            None,
+            vcr_client,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_missing_database_warns() {
+        let db = ZammDatabase::new(None, None);
+        let llm_call = chat_without_saving(&db).await;
+        assert_eq!(
+            llm_call.warning.as_deref(),
+            Some("This call was not saved to the database: Database is not available")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_warns() {
+        let db = setup_zamm_db();
+        diesel::sql_query("DROP TABLE llm_calls")
+            .execute(&mut *db.connection().unwrap())
+            .unwrap();
+
+        let llm_call = chat_without_saving(&db).await;
+        let warning = llm_call.warning.unwrap();
+        assert!(
+            warning.contains("llm_calls"),
+            "Unexpected warning: {warning}"
+        );
+        assert!(db.last_error().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_budget_blocks_chat_unless_ignored() {
+        use crate::commands::preferences::{Budget, BudgetPeriod};
+
+        let db = setup_zamm_db();
+        let sample_path = "api/sample-calls/chat-start-conversation.yaml";
+        let previous_call = LlmCall {
+            timestamp: chrono::Utc::now().naive_utc(),
+            ..read_sample_llm_call(sample_path, "2024-03-01T10:00:00")
+        };
+        insert_llm_call(&db, &previous_call).await;
+        let preferences = Preferences {
+            budgets: Some(vec![Budget {
+                provider: Service::OpenAI,
+                period: BudgetPeriod::Daily,
+                max_cost: None,
+                max_tokens: Some(10),
+            }]),
+            ..Preferences::default()
+        };
+
+        for ignore_budget in [None, Some(true)] {
+            let (api_keys, vcr_client) =
+                setup_vcr("api/sample-call-requests/start-conversation.json");
+            let request = parse_request(&read_sample(sample_path).request[1]);
+            let result = chat_helper(
+                &api_keys,
+                &db,
+                &setup_blob_store(),
+                &preferences,
+                request.provider,
+                request.llm,
+                request.temperature,
+                request.prompt,
+                None,
+                None,
+                ignore_budget,
+                vcr_client,
+            )
+            .await;
+            match ignore_budget {
+                None => {
+                    assert!(matches!(result, Err(Error::TokenBudgetExceeded { .. })))
+                }
+                Some(_) => assert!(result.is_ok(), "Error: {:?}", result.err()),
+            }
+        }
+    }
 }
@@ -0,0 +1,384 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::budget::{check_call_budgets, CallEstimate};
+use crate::commands::llms::cancel::{aborted_call, cancellable_chat};
+use crate::commands::llms::chat::{
+    get_openai_config, get_previous_call_id, openai_request, persist_llm_call,
+};
+use crate::commands::llms::images::{load_images, store_images};
+use crate::commands::llms::pricing::compute_cost;
+use crate::commands::llms::retry::llm_http_client;
+use crate::commands::preferences::{get_preferences_helper, Preferences};
+use crate::commands::Error;
+use crate::models::llm_calls::{
+    ChatMessage, ChatParameters, ChatPrompt, CompletionChoices, EntityId, Llm, LlmCall,
+    Prompt, Request, Response, TokenMetadata,
+};
+use crate::setup::api_keys::Service;
+use crate::setup::blobs::BlobStore;
+use crate::setup::get_blob_store;
+use crate::{ZammActiveChats, ZammApiKeys, ZammDatabase};
+use async_openai::config::Config;
+use async_openai::types::{
+    CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionStreamResponse,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::{Manager, State, Window};
+use uuid::Uuid;
+
+/// Name of the Tauri event that incremental chat completions are emitted under.
+pub const CHAT_STREAM_EVENT: &str = "chat-stream";
+
+const SSE_DATA_PREFIX: &str = "data:";
+const SSE_DONE: &str = "[DONE]";
+
+/// A piece of a streamed completion, labelled with the request ID that the caller
+/// passed in so that concurrent streams can be told apart.
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct ChatStreamDelta {
+    pub request_id: Uuid,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+/// A streaming request that also asks for token usage, which gets sent in one last
+/// chunk with no choices.
+#[derive(Debug, Serialize)]
+struct StreamingRequest<'a> {
+    #[serde(flatten)]
+    request: &'a CreateChatCompletionRequest,
+    stream_options: StreamOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamUsage {
+    usage: Option<CompletionUsage>,
+}
+
+/// Splits a server-sent event byte stream into the payloads of its `data` fields.
+#[derive(Debug, Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    data: String,
+}
+
+impl SseParser {
+    fn push(&mut self, bytes: &[u8]) -> ZammResult<Vec<String>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = vec![];
+        while let Some(newline) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = std::str::from_utf8(&line_bytes)
+                .map_err(|e| Error::UnexpectedOpenAiResponse {
+                    reason: format!("Stream is not valid UTF-8: {e}"),
+                })?
+                .trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                // a blank line dispatches the event accumulated so far
+                if !self.data.is_empty() {
+                    events.push(std::mem::take(&mut self.data));
+                }
+            } else if let Some(data) = line.strip_prefix(SSE_DATA_PREFIX) {
+                if !self.data.is_empty() {
+                    self.data.push('\n');
+                }
+                self.data.push_str(data.strip_prefix(' ').unwrap_or(data));
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn chat_stream_helper<F>(
+    zamm_api_keys: &ZammApiKeys,
+    zamm_db: &ZammDatabase,
+    blob_store: &BlobStore,
+    preferences: &Preferences,
+    provider: Service,
+    llm: String,
+    temperature: Option<f32>,
+    prompt: Vec<ChatMessage>,
+    parameters: Option<ChatParameters>,
+    conversation_id: Option<Uuid>,
+    ignore_budget: Option<bool>,
+    request_id: Uuid,
+    http_client: reqwest_middleware::ClientWithMiddleware,
+    emit: F,
+) -> ZammResult<LlmCall>
+where
+    F: Fn(&ChatStreamDelta) -> ZammResult<()>,
+{
+    // neither the keys nor a database connection are held on to while waiting on
+    // the LLM, so that other commands can use them in the meantime
+    let api_keys = zamm_api_keys.0.lock().await.clone();
+    let config = get_openai_config(&api_keys, preferences, &provider)?;
+    let conversation_id = conversation_id.map(|uuid| EntityId { uuid });
+    let previous_call_id = get_previous_call_id(zamm_db, conversation_id.as_ref())?;
+    let id = EntityId {
+        uuid: Uuid::new_v4(),
+    };
+    let requested_model = llm;
+    let requested_temperature = temperature.unwrap_or(1.0);
+    let parameters = parameters.unwrap_or_default();
+    if parameters.n.is_some_and(|n| n > 1) {
+        return Err(Error::UnsupportedFeature {
+            service: provider,
+            feature: "Streaming multiple completions".to_string(),
+        });
+    }
+    if parameters
+        .tools
+        .as_ref()
+        .is_some_and(|tools| !tools.0.is_empty())
+    {
+        return Err(Error::UnsupportedFeature {
+            service: provider,
+            feature: "Streaming with tools".to_string(),
+        });
+    }
+
+    let prompt = store_images(blob_store, prompt)?;
+    let budget_warning = if ignore_budget.unwrap_or(false) {
+        None
+    } else {
+        let estimate = CallEstimate::new(
+            preferences,
+            &provider,
+            &requested_model,
+            &prompt,
+            &parameters,
+        );
+        check_call_budgets(zamm_db, preferences, &provider, &estimate)?
+    };
+    let mut request = openai_request(
+        &requested_model,
+        requested_temperature,
+        &load_images(blob_store, &prompt)?,
+        &parameters,
+        &[],
+    )?;
+    request.stream = Some(true);
+    let response = http_client
+        .post(config.url("/chat/completions"))
+        .headers(config.headers())
+        .json(&StreamingRequest {
+            request: &request,
+            stream_options: StreamOptions {
+                include_usage: true,
+            },
+        })
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut parser = SseParser::default();
+    let mut body = response.bytes_stream();
+    let mut model = None;
+    let mut completion = String::new();
+    let mut finish_reason = None;
+    let mut usage = None;
+    'stream: while let Some(bytes) = body.next().await {
+        for data in parser.push(&bytes?)? {
+            if data == SSE_DONE {
+                break 'stream;
+            }
+
+            let chunk: CreateChatCompletionStreamResponse =
+                serde_json::from_str(&data)?;
+            model.get_or_insert(chunk.model);
+            let StreamUsage { usage: chunk_usage } = serde_json::from_str(&data)?;
+            if chunk_usage.is_some() {
+                usage = chunk_usage;
+            }
+            let Some(choice) = chunk.choices.into_iter().next() else {
+                continue;
+            };
+            if let Some(reason) = choice.finish_reason {
+                finish_reason = Some(reason.into());
+            }
+            let delta_text = choice.delta.content.unwrap_or_default();
+            if !delta_text.is_empty() {
+                completion.push_str(&delta_text);
+                emit(&ChatStreamDelta {
+                    request_id,
+                    text: delta_text,
+                })?;
+            }
+        }
+    }
+
+    let llm = Llm {
+        provider,
+        name: model.ok_or(Error::UnexpectedOpenAiResponse {
+            reason: "Stream ended without any completion chunks".to_owned(),
+        })?,
+        requested: requested_model.to_owned(),
+    };
+    let tokens = TokenMetadata {
+        prompt: usage.as_ref().map(|usage| usage.prompt_tokens as i32),
+        response: usage.as_ref().map(|usage| usage.completion_tokens as i32),
+        total: usage.as_ref().map(|usage| usage.total_tokens as i32),
+    };
+    let cost = compute_cost(preferences, &llm, &tokens);
+    let llm_call = LlmCall {
+        id,
+        timestamp: chrono::Utc::now().naive_utc(),
+        llm,
+        request: Request {
+            temperature: requested_temperature,
+            prompt: Prompt::Chat(ChatPrompt { messages: prompt }),
+            parameters,
+        },
+        response: Response {
+            completion: ChatMessage::AI { text: completion },
+            finish_reason,
+            choices: CompletionChoices::default(),
+        },
+        tokens,
+        cost,
+        conversation_id,
+        previous_call_id,
+        warning: budget_warning,
+    };
+
+    Ok(persist_llm_call(zamm_db, llm_call))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tauri::command(async)]
+#[specta]
+pub async fn chat_stream(
+    window: Window,
+    api_keys: State<'_, ZammApiKeys>,
+    database: State<'_, ZammDatabase>,
+    active_chats: State<'_, ZammActiveChats>,
+    provider: Service,
+    llm: String,
+    temperature: Option<f32>,
+    prompt: Vec<ChatMessage>,
+    parameters: Option<ChatParameters>,
+    conversation_id: Option<Uuid>,
+    ignore_budget: Option<bool>,
+    request_id: Option<Uuid>,
+) -> ZammResult<LlmCall> {
+    let preferences =
+        get_preferences_helper(&window.app_handle().path_resolver().app_config_dir());
+    let client_with_middleware = llm_http_client(&preferences)?;
+    let blob_store = get_blob_store(&database);
+    let request_id = request_id.unwrap_or_else(Uuid::new_v4);
+    let aborted = aborted_call(
+        request_id,
+        &provider,
+        &llm,
+        temperature,
+        &prompt,
+        parameters.as_ref(),
+        conversation_id,
+    );
+    let request = chat_stream_helper(
+        &api_keys,
+        &database,
+        &blob_store,
+        &preferences,
+        provider,
+        llm,
+        temperature,
+        prompt,
+        parameters,
+        conversation_id,
+        ignore_budget,
+        request_id,
+        client_with_middleware,
+        |delta| Ok(window.emit(CHAT_STREAM_EVENT, delta)?),
+    );
+    cancellable_chat(&active_chats, &database, &blob_store, aborted, request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{
+        check_llm_call_result, parse_request, read_sample, setup_blob_store, setup_vcr,
+        setup_zamm_db,
+    };
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_sse_parser_across_chunk_boundaries() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"data: {\"a\":").unwrap().is_empty());
+        assert_eq!(
+            parser.push(b" 1}\r\n\r\ndata: [DONE]\n").unwrap(),
+            vec!["{\"a\": 1}".to_string()]
+        );
+        assert_eq!(parser.push(b"\n").unwrap(), vec![SSE_DONE.to_string()]);
+    }
+
+    async fn test_llm_api_stream(recording_path: &str, sample_path: &str) {
+        let (api_keys, vcr_client) = setup_vcr(recording_path);
+        let db = setup_zamm_db();
+        // end dependencies setup
+
+        let sample = read_sample(sample_path);
+        assert_eq!(sample.request.len(), 2);
+        assert_eq!(sample.request[0], "chat_stream");
+
+        let request = parse_request(&sample.request[1]);
+        let request_id = Uuid::new_v4();
+        let deltas = Mutex::new(Vec::<ChatStreamDelta>::new());
+
+        let result = chat_stream_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            request.provider,
+            request.llm,
+            request.temperature,
+            request.prompt,
+            request.parameters,
+            request.conversation_id,
+            request.ignore_budget,
+            request_id,
+            vcr_client,
+            |delta| {
+                deltas.lock().unwrap().push(delta.clone());
+                Ok(())
+            },
+        )
+        .await;
+
+        // check that the deltas emitted add up to the final completion
+        let deltas = deltas.into_inner().unwrap();
+        assert!(deltas.len() > 1);
+        assert!(deltas.iter().all(|delta| delta.request_id == request_id));
+        if let Ok(llm_call) = &result {
+            let streamed_text: String =
+                deltas.iter().map(|delta| delta.text.as_str()).collect();
+            assert_eq!(
+                llm_call.response.completion,
+                ChatMessage::AI {
+                    text: streamed_text
+                }
+            );
+        }
+
+        check_llm_call_result(&db, &sample, result).await;
+    }
+
+    #[tokio::test]
+    async fn test_stream_start_conversation() {
+        test_llm_api_stream(
+            "api/sample-call-requests/stream-start-conversation.json",
+            "api/sample-calls/chat_stream-start-conversation.yaml",
+        )
+        .await;
+    }
+}
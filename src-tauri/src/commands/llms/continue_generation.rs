@@ -0,0 +1,326 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::llms::budget::{check_call_budgets, CallEstimate};
+use crate::commands::llms::cancel::cancellable_update;
+use crate::commands::llms::chat::{request_completion, response_from_choices};
+use crate::commands::llms::pricing::compute_cost;
+use crate::commands::llms::retry::llm_http_client;
+use crate::commands::preferences::{get_preferences_helper, Preferences};
+use crate::commands::Error;
+use crate::models::llm_calls::{
+    ChatMessage, ChatParameters, ChatPrompt, CompletionChoices, EntityId, FinishReason,
+    Llm, LlmCall, LlmCallRow, Prompt, Response, TokenMetadata,
+};
+use crate::schema::llm_calls;
+use crate::setup::api_keys::Service;
+use crate::setup::blobs::BlobStore;
+use crate::setup::get_blob_store;
+use crate::{ZammActiveChats, ZammApiKeys, ZammDatabase};
+use diesel::prelude::*;
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where you left off, without repeating anything.";
+
+/// Builds the prompt that asks the model to pick up where the partial completion
+/// left off, along with the text that the continuation should be appended to.
+fn continuation_prompt(
+    provider: &Service,
+    messages: &[ChatMessage],
+    partial: &str,
+) -> (Vec<ChatMessage>, String) {
+    let mut prompt = messages.to_vec();
+    match provider {
+        // Anthropic continues a trailing assistant message directly, but rejects
+        // one that ends in whitespace
+        Service::Anthropic => {
+            let prefix = partial.trim_end().to_string();
+            prompt.push(ChatMessage::AI {
+                text: prefix.clone(),
+            });
+            (prompt, prefix)
+        }
+        _ => {
+            prompt.push(ChatMessage::AI {
+                text: partial.to_string(),
+            });
+            prompt.push(ChatMessage::Human {
+                text: CONTINUE_PROMPT.to_string(),
+                images: vec![],
+            });
+            (prompt, partial.to_string())
+        }
+    }
+}
+
+/// Usage is only known in total if it is known for both parts.
+fn add_usage<T: std::ops::Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+    Some(a? + b?)
+}
+
+async fn continue_generation_helper(
+    zamm_api_keys: &ZammApiKeys,
+    zamm_db: &ZammDatabase,
+    blob_store: &BlobStore,
+    preferences: &Preferences,
+    id: Uuid,
+    ignore_budget: Option<bool>,
+    http_client: reqwest_middleware::ClientWithMiddleware,
+) -> ZammResult<LlmCall> {
+    let original_id = EntityId { uuid: id };
+    let original: LlmCall = {
+        let conn = &mut *zamm_db.connection()?;
+        llm_calls::table
+            .find(&original_id)
+            .first::<LlmCallRow>(conn)?
+            .into()
+    };
+
+    let partial = match &original.response.completion {
+        ChatMessage::AI { text }
+            if original.response.finish_reason == Some(FinishReason::Length) =>
+        {
+            text
+        }
+        _ => return Err(Error::NotTruncated { id }),
+    };
+    let Prompt::Chat(ChatPrompt { messages }) = &original.request.prompt;
+    let (prompt, prefix) =
+        continuation_prompt(&original.llm.provider, messages, partial);
+
+    let parameters = ChatParameters {
+        n: None,
+        ..original.request.parameters.clone()
+    };
+    let provider = &original.llm.provider;
+    let budget_warning = if ignore_budget.unwrap_or(false) {
+        None
+    } else {
+        let estimate = CallEstimate::new(
+            preferences,
+            provider,
+            &original.llm.requested,
+            &prompt,
+            &parameters,
+        );
+        check_call_budgets(zamm_db, preferences, provider, &estimate)?
+    };
+
+    // the continuation is only ever sent, never recorded, so that the request to
+    // continue doesn't show up in the history
+    let api_keys = zamm_api_keys.0.lock().await.clone();
+    let continuation = request_completion(
+        &api_keys,
+        blob_store,
+        preferences,
+        provider,
+        &original.llm.requested,
+        original.request.temperature,
+        &prompt,
+        &parameters,
+        &[],
+        http_client,
+    )
+    .await?;
+    let continuation_llm = Llm {
+        provider: provider.clone(),
+        name: continuation.model,
+        requested: original.llm.requested.clone(),
+    };
+    let continuation_cost =
+        compute_cost(preferences, &continuation_llm, &continuation.tokens);
+    let continuation_tokens = continuation.tokens;
+    let continuation = response_from_choices(continuation.choices)?;
+    let continuation_text = match &continuation.completion {
+        ChatMessage::AI { text } => text,
+        _ => {
+            return Err(Error::UnexpectedOpenAiResponse {
+                reason: "Continuation is not a text completion".to_string(),
+            })
+        }
+    };
+
+    let stitched = LlmCall {
+        response: Response {
+            completion: ChatMessage::AI {
+                text: format!("{prefix}{continuation_text}"),
+            },
+            finish_reason: continuation.finish_reason,
+            choices: CompletionChoices::default(),
+        },
+        tokens: TokenMetadata {
+            prompt: add_usage(original.tokens.prompt, continuation_tokens.prompt),
+            response: add_usage(original.tokens.response, continuation_tokens.response),
+            total: add_usage(original.tokens.total, continuation_tokens.total),
+        },
+        cost: add_usage(original.cost, continuation_cost),
+        warning: budget_warning,
+        ..original
+    };
+    // the continuation's usage counts towards the original call, unless that call
+    // got continued by someone else in the meantime
+    let conn = &mut *zamm_db.connection()?;
+    conn.transaction(|conn| {
+        let updated = diesel::update(
+            llm_calls::table
+                .find(&original_id)
+                .filter(llm_calls::finish_reason.eq(Some(FinishReason::Length))),
+        )
+        .set((
+            llm_calls::completion.eq(&stitched.response.completion),
+            llm_calls::finish_reason.eq(&stitched.response.finish_reason),
+            llm_calls::choices.eq(None::<CompletionChoices>),
+            llm_calls::prompt_tokens.eq(stitched.tokens.prompt),
+            llm_calls::response_tokens.eq(stitched.tokens.response),
+            llm_calls::total_tokens.eq(stitched.tokens.total),
+            llm_calls::cost.eq(stitched.cost),
+        ))
+        .execute(conn)?;
+        match updated {
+            0 => Err(Error::NotTruncated { id }),
+            _ => Ok(()),
+        }
+    })?;
+    Ok(stitched)
+}
+
+/// Asks the model to resume a completion that was cut off at its token limit, and
+/// appends the result and its usage to the original call. It can be cancelled with
+/// `cancel_chat` using the given request ID.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command(async)]
+#[specta]
+pub async fn continue_generation(
+    app_handle: tauri::AppHandle,
+    api_keys: State<'_, ZammApiKeys>,
+    database: State<'_, ZammDatabase>,
+    active_chats: State<'_, ZammActiveChats>,
+    id: Uuid,
+    ignore_budget: Option<bool>,
+    request_id: Option<Uuid>,
+) -> ZammResult<LlmCall> {
+    let preferences =
+        get_preferences_helper(&app_handle.path_resolver().app_config_dir());
+    let client_with_middleware = llm_http_client(&preferences)?;
+    let blob_store = get_blob_store(&database);
+    let request = continue_generation_helper(
+        &api_keys,
+        &database,
+        &blob_store,
+        &preferences,
+        id,
+        ignore_budget,
+        client_with_middleware,
+    );
+    let request_id = request_id.unwrap_or_else(Uuid::new_v4);
+    cancellable_update(&active_chats, request_id, request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{
+        get_llm_call, insert_llm_call, read_sample_llm_call, setup_vcr,
+    };
+    use crate::test_helpers::{setup_blob_store, setup_zamm_db};
+
+    fn truncated_call() -> LlmCall {
+        let sample = read_sample_llm_call(
+            "api/sample-calls/chat-start-conversation.yaml",
+            "2024-03-23T09:41:20",
+        );
+        let mut request = sample.request.clone();
+        request.parameters.max_tokens = Some(8);
+        LlmCall {
+            request,
+            response: Response {
+                completion: ChatMessage::AI {
+                    text: "Yes, it works. How can I".to_string(),
+                },
+                finish_reason: Some(FinishReason::Length),
+                choices: CompletionChoices::default(),
+            },
+            ..sample
+        }
+    }
+
+    #[test]
+    fn test_anthropic_continuation_is_prefilled() {
+        let (prompt, prefix) =
+            continuation_prompt(&Service::Anthropic, &[], "Once upon a ");
+        assert_eq!(prefix, "Once upon a");
+        assert_eq!(
+            prompt,
+            vec![ChatMessage::AI {
+                text: "Once upon a".to_string()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_continue_truncated_call() {
+        let (api_keys, vcr_client) =
+            setup_vcr("api/sample-call-requests/continue-generation.json");
+        let db = setup_zamm_db();
+        let original = truncated_call();
+        insert_llm_call(&db, &original).await;
+
+        let stitched = continue_generation_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            original.id.uuid,
+            None,
+            vcr_client,
+        )
+        .await
+        .unwrap();
+        let expected_completion = ChatMessage::AI {
+            text: "Yes, it works. How can I assist you today?".to_string(),
+        };
+        assert_eq!(stitched.response.completion, expected_completion);
+        assert_eq!(stitched.response.finish_reason, Some(FinishReason::Stop));
+
+        let stored = get_llm_call(&db, &original.id).await;
+        assert_eq!(stored.response.completion, expected_completion);
+        assert_eq!(stored.response.finish_reason, Some(FinishReason::Stop));
+
+        assert_eq!(stored.tokens.prompt, Some(32 + 60));
+        assert_eq!(stored.tokens.response, Some(12 + 5));
+        assert_eq!(stored.tokens.total, Some(44 + 65));
+        assert!((stored.cost.unwrap() - (0.00168 + 0.0021)).abs() < 1e-9);
+
+        // the request to continue is nowhere to be found in the history
+        let num_calls: i64 = {
+            let mut conn = db.connection().unwrap();
+            llm_calls::table.count().get_result(&mut *conn).unwrap()
+        };
+        assert_eq!(num_calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_continue_finished_call() {
+        let (api_keys, vcr_client) =
+            setup_vcr("api/sample-call-requests/continue-generation.json");
+        let db = setup_zamm_db();
+        let finished = read_sample_llm_call(
+            "api/sample-calls/chat-start-conversation.yaml",
+            "2024-03-23T09:41:20",
+        );
+        insert_llm_call(&db, &finished).await;
+
+        let result = continue_generation_helper(
+            &api_keys,
+            &db,
+            &setup_blob_store(),
+            &Preferences::default(),
+            finished.id.uuid,
+            None,
+            vcr_client,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::NotTruncated { .. })));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::commands::errors::ZammResult;
+use crate::models::llm_calls::{EntityId, LlmCall, LlmCallRow};
+use crate::schema::llm_calls;
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use specta::specta;
+use tauri::State;
+use uuid::Uuid;
+
+async fn get_llm_call_helper(zamm_db: &ZammDatabase, id: Uuid) -> ZammResult<LlmCall> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let llm_call_row = llm_calls::table
+        .find(EntityId { uuid: id })
+        .first::<LlmCallRow>(conn)?;
+    Ok(llm_call_row.into())
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn get_llm_call(
+    database: State<'_, ZammDatabase>,
+    id: Uuid,
+) -> ZammResult<LlmCall> {
+    get_llm_call_helper(&database, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::test_helpers::setup_zamm_db;
+
+    #[tokio::test]
+    async fn test_get_llm_call() {
+        let db = setup_zamm_db();
+        let llm_call = read_sample_llm_call(
+            "api/sample-calls/chat-start-conversation.yaml",
+            "2024-03-01T10:00:00",
+        );
+        insert_llm_call(&db, &llm_call).await;
+
+        let retrieved = get_llm_call_helper(&db, llm_call.id.uuid).await.unwrap();
+        assert_eq!(
+            serde_json::to_string(&retrieved).unwrap(),
+            serde_json::to_string(&llm_call).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_nonexistent_llm_call() {
+        let db = setup_zamm_db();
+        assert!(get_llm_call_helper(&db, Uuid::new_v4()).await.is_err());
+    }
+}
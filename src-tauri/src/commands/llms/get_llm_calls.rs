@@ -0,0 +1,258 @@
+use crate::commands::errors::ZammResult;
+use crate::models::llm_calls::{EntityId, LlmCall, LlmCallRow};
+use crate::schema::llm_calls;
+use crate::setup::api_keys::Service;
+use crate::ZammDatabase;
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::State;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Criteria that every returned LLM call must satisfy. Unset fields match anything.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct LlmCallFilter {
+    pub provider: Option<Service>,
+    /// Matches either the model that was requested or the one that responded.
+    pub llm: Option<String>,
+    pub start_time: Option<NaiveDateTime>,
+    pub end_time: Option<NaiveDateTime>,
+    pub min_tokens: Option<i32>,
+    pub max_tokens: Option<i32>,
+}
+
+/// Where the previous page left off. Calls can share a timestamp, so the ID breaks
+/// ties.
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct LlmCallCursor {
+    #[serde(flatten)]
+    pub id: EntityId,
+    pub timestamp: NaiveDateTime,
+}
+
+impl From<&LlmCall> for LlmCallCursor {
+    fn from(llm_call: &LlmCall) -> Self {
+        LlmCallCursor {
+            id: llm_call.id.clone(),
+            timestamp: llm_call.timestamp,
+        }
+    }
+}
+
+/// Returns LLM calls from newest to oldest. To get the next page, pass in the
+/// timestamp and ID of the last call on the current page as `before`.
+async fn get_llm_calls_helper(
+    zamm_db: &ZammDatabase,
+    filter: LlmCallFilter,
+    before: Option<LlmCallCursor>,
+    limit: Option<i64>,
+) -> ZammResult<Vec<LlmCall>> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let mut query = llm_calls::table.into_boxed();
+    if let Some(provider) = filter.provider {
+        query = query.filter(llm_calls::provider.eq(provider));
+    }
+    if let Some(llm) = filter.llm {
+        query = query.filter(
+            llm_calls::llm
+                .eq(llm.clone())
+                .or(llm_calls::llm_requested.eq(llm)),
+        );
+    }
+    if let Some(start_time) = filter.start_time {
+        query = query.filter(llm_calls::timestamp.ge(start_time));
+    }
+    if let Some(end_time) = filter.end_time {
+        query = query.filter(llm_calls::timestamp.le(end_time));
+    }
+    if let Some(min_tokens) = filter.min_tokens {
+        query = query.filter(llm_calls::total_tokens.ge(min_tokens));
+    }
+    if let Some(max_tokens) = filter.max_tokens {
+        query = query.filter(llm_calls::total_tokens.le(max_tokens));
+    }
+    if let Some(before) = before {
+        query = query.filter(
+            llm_calls::timestamp
+                .lt(before.timestamp)
+                .or(llm_calls::timestamp
+                    .eq(before.timestamp)
+                    .and(llm_calls::id.lt(before.id))),
+        );
+    }
+
+    let llm_calls = query
+        .order((llm_calls::timestamp.desc(), llm_calls::id.desc()))
+        .limit(limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .load::<LlmCallRow>(conn)?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+    Ok(llm_calls)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn get_llm_calls(
+    database: State<'_, ZammDatabase>,
+    filter: LlmCallFilter,
+    before: Option<LlmCallCursor>,
+    limit: Option<i64>,
+) -> ZammResult<Vec<LlmCall>> {
+    get_llm_calls_helper(&database, filter, before, limit).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::test_helpers::setup_zamm_db;
+
+    /// Sets up a database with one call from each sample, an hour apart.
+    async fn setup_history() -> (ZammDatabase, Vec<LlmCall>) {
+        let db = setup_zamm_db();
+        let samples = [
+            ("chat-start-conversation", "2024-03-01T10:00:00"),
+            ("chat-continue-conversation", "2024-03-01T11:00:00"),
+            ("chat-anthropic-start-conversation", "2024-03-01T12:00:00"),
+            ("chat-ollama-start-conversation", "2024-03-01T13:00:00"),
+        ];
+        let mut llm_calls = vec![];
+        for (sample, timestamp) in samples {
+            let llm_call = read_sample_llm_call(
+                &format!("api/sample-calls/{sample}.yaml"),
+                timestamp,
+            );
+            insert_llm_call(&db, &llm_call).await;
+            llm_calls.push(llm_call);
+        }
+        (db, llm_calls)
+    }
+
+    fn ids(llm_calls: &[LlmCall]) -> Vec<String> {
+        llm_calls.iter().map(|call| call.id.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_newest_first() {
+        let (db, inserted) = setup_history().await;
+        let results = get_llm_calls_helper(&db, LlmCallFilter::default(), None, None)
+            .await
+            .unwrap();
+        let expected: Vec<LlmCall> = inserted.into_iter().rev().collect();
+        assert_eq!(ids(&results), ids(&expected));
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let (db, inserted) = setup_history().await;
+        let first_page =
+            get_llm_calls_helper(&db, LlmCallFilter::default(), None, Some(3))
+                .await
+                .unwrap();
+        assert_eq!(first_page.len(), 3);
+
+        let second_page = get_llm_calls_helper(
+            &db,
+            LlmCallFilter::default(),
+            first_page.last().map(LlmCallCursor::from),
+            Some(3),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&second_page), ids(&inserted[..1]));
+    }
+
+    #[tokio::test]
+    async fn test_pagination_with_shared_timestamps() {
+        let db = setup_zamm_db();
+        for _ in 0..5 {
+            let llm_call = read_sample_llm_call(
+                "api/sample-calls/chat-start-conversation.yaml",
+                "2024-03-01T10:00:00",
+            );
+            insert_llm_call(&db, &llm_call).await;
+        }
+
+        let mut seen = vec![];
+        let mut before = None;
+        loop {
+            let page = get_llm_calls_helper(
+                &db,
+                LlmCallFilter::default(),
+                before.clone(),
+                Some(2),
+            )
+            .await
+            .unwrap();
+            if page.is_empty() {
+                break;
+            }
+            before = page.last().map(LlmCallCursor::from);
+            seen.extend(ids(&page));
+        }
+        assert_eq!(seen.len(), 5);
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_filter_by_provider_and_model() {
+        let (db, inserted) = setup_history().await;
+        let openai_filter = LlmCallFilter {
+            provider: Some(Service::OpenAI),
+            ..LlmCallFilter::default()
+        };
+        let results = get_llm_calls_helper(&db, openai_filter, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&results),
+            ids(&[inserted[1].clone(), inserted[0].clone()])
+        );
+
+        let model_filter = LlmCallFilter {
+            llm: Some("llama2".to_string()),
+            ..LlmCallFilter::default()
+        };
+        let results = get_llm_calls_helper(&db, model_filter, None, None)
+            .await
+            .unwrap();
+        assert_eq!(ids(&results), ids(&inserted[3..]));
+    }
+
+    #[tokio::test]
+    async fn test_filter_by_time_and_tokens() {
+        let (db, inserted) = setup_history().await;
+        let time_filter = LlmCallFilter {
+            start_time: Some(inserted[1].timestamp),
+            end_time: Some(inserted[2].timestamp),
+            ..LlmCallFilter::default()
+        };
+        let results = get_llm_calls_helper(&db, time_filter, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&results),
+            ids(&[inserted[2].clone(), inserted[1].clone()])
+        );
+
+        let token_filter = LlmCallFilter {
+            min_tokens: Some(50),
+            max_tokens: Some(60),
+            ..LlmCallFilter::default()
+        };
+        let results = get_llm_calls_helper(&db, token_filter, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&results),
+            ids(&[inserted[3].clone(), inserted[2].clone()])
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use crate::models::llm_calls::{ChatImage, ChatMessage};
+use crate::setup::blobs::BlobStore;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fs;
+use std::path::Path;
+
+fn media_type_for(path: &Path) -> ZammResult<String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let media_type = match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => {
+            return Err(Error::InvalidImage {
+                reason: format!("Unsupported image type for {}", path.display()),
+            })
+        }
+    };
+    Ok(media_type.to_string())
+}
+
+fn read_image_file(path: &Path) -> ZammResult<Vec<u8>> {
+    fs::read(path).map_err(|e| Error::InvalidImage {
+        reason: format!("Cannot read {}: {e}", path.display()),
+    })
+}
+
+fn store_image(blob_store: &BlobStore, image: ChatImage) -> ZammResult<ChatImage> {
+    let (media_type, data) = match image {
+        ChatImage::Path { path } => {
+            let path = Path::new(&path);
+            (media_type_for(path)?, read_image_file(path)?)
+        }
+        ChatImage::Base64 { media_type, data } => {
+            let data = BASE64.decode(data).map_err(|e| Error::InvalidImage {
+                reason: format!("Image data is not valid base64: {e}"),
+            })?;
+            (media_type, data)
+        }
+        blob @ ChatImage::Blob { .. } => return Ok(blob),
+    };
+    let hash = blob_store.put(&data)?;
+    Ok(ChatImage::Blob { media_type, hash })
+}
+
+fn load_image(blob_store: &BlobStore, image: &ChatImage) -> ZammResult<ChatImage> {
+    match image {
+        ChatImage::Blob { media_type, hash } => Ok(ChatImage::Base64 {
+            media_type: media_type.clone(),
+            data: BASE64.encode(blob_store.get(hash)?),
+        }),
+        ChatImage::Path { path } => {
+            let path = Path::new(path);
+            Ok(ChatImage::Base64 {
+                media_type: media_type_for(path)?,
+                data: BASE64.encode(read_image_file(path)?),
+            })
+        }
+        ChatImage::Base64 { .. } => Ok(image.clone()),
+    }
+}
+
+/// Moves any images in the prompt into the blob store, so that only their hashes
+/// end up in the database.
+pub fn store_images(
+    blob_store: &BlobStore,
+    prompt: Vec<ChatMessage>,
+) -> ZammResult<Vec<ChatMessage>> {
+    prompt
+        .into_iter()
+        .map(|message| match message {
+            ChatMessage::Human { text, images } => Ok(ChatMessage::Human {
+                text,
+                images: images
+                    .into_iter()
+                    .map(|image| store_image(blob_store, image))
+                    .collect::<ZammResult<Vec<ChatImage>>>()?,
+            }),
+            _ => Ok(message),
+        })
+        .collect()
+}
+
+/// Inlines the data for any images in the prompt so that it can be sent to an LLM
+/// provider.
+pub fn load_images(
+    blob_store: &BlobStore,
+    prompt: &[ChatMessage],
+) -> ZammResult<Vec<ChatMessage>> {
+    prompt
+        .iter()
+        .map(|message| match message {
+            ChatMessage::Human { text, images } => Ok(ChatMessage::Human {
+                text: text.clone(),
+                images: images
+                    .iter()
+                    .map(|image| load_image(blob_store, image))
+                    .collect::<ZammResult<Vec<ChatImage>>>()?,
+            }),
+            _ => Ok(message.clone()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::get_temp_test_dir;
+
+    const SCREENSHOT_BYTES: &[u8] = b"\x89PNG not really a screenshot";
+
+    #[test]
+    fn test_images_stored_by_hash_and_loaded_back() {
+        let test_dir = get_temp_test_dir("store_and_load_images");
+        let screenshot_path = test_dir.join("screenshot.PNG");
+        fs::write(&screenshot_path, SCREENSHOT_BYTES).unwrap();
+        let blob_store = BlobStore::new(test_dir.join("blobs"));
+
+        let prompt = vec![ChatMessage::Human {
+            text: "What's in these?".to_string(),
+            images: vec![
+                ChatImage::Path {
+                    path: screenshot_path.to_str().unwrap().to_string(),
+                },
+                ChatImage::Base64 {
+                    media_type: "image/jpeg".to_string(),
+                    data: BASE64.encode(SCREENSHOT_BYTES),
+                },
+            ],
+        }];
+
+        let stored = store_images(&blob_store, prompt).unwrap();
+        let ChatMessage::Human { images, .. } = &stored[0] else {
+            panic!("Unexpected message type");
+        };
+        // identical data only gets stored once
+        let hashes: Vec<&str> = images
+            .iter()
+            .map(|image| match image {
+                ChatImage::Blob { hash, .. } => hash.as_str(),
+                _ => panic!("Image not moved into blob store"),
+            })
+            .collect();
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(blob_store.get(hashes[0]).unwrap(), SCREENSHOT_BYTES);
+
+        let loaded = load_images(&blob_store, &stored).unwrap();
+        let ChatMessage::Human { images, .. } = &loaded[0] else {
+            panic!("Unexpected message type");
+        };
+        assert_eq!(
+            images[0],
+            ChatImage::Base64 {
+                media_type: "image/png".to_string(),
+                data: BASE64.encode(SCREENSHOT_BYTES),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unsupported_image_type_rejected() {
+        let test_dir = get_temp_test_dir("unsupported_image_type");
+        let blob_store = BlobStore::new(test_dir.join("blobs"));
+        let notes_path = test_dir.join("notes.txt");
+        fs::write(&notes_path, "hello").unwrap();
+
+        let result = store_image(
+            &blob_store,
+            ChatImage::Path {
+                path: notes_path.to_str().unwrap().to_string(),
+            },
+        );
+        assert!(matches!(result, Err(Error::InvalidImage { .. })));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::commands::llms::tools::available_tools;
+use serde::{Deserialize, Serialize};
+use specta::specta;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct ToolInfo {
+    pub name: String,
+    pub description: String,
+}
+
+/// Lists the local tools that can be enabled for a chat.
+#[tauri::command(async)]
+#[specta]
+pub fn list_tools() -> Vec<ToolInfo> {
+    available_tools()
+        .iter()
+        .map(|tool| ToolInfo {
+            name: tool.name().to_string(),
+            description: tool.description().to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::read_sample;
+
+    #[test]
+    fn test_list_tools() {
+        let sample = read_sample("api/sample-calls/list_tools.yaml");
+        assert_eq!(sample.request, vec!["list_tools"]);
+
+        let actual_json = serde_json::to_string_pretty(&list_tools()).unwrap();
+        assert_eq!(actual_json, sample.response.message.trim());
+    }
+}
@@ -1,3 +1,25 @@
-mod chat;
+pub mod anthropic;
+mod budget;
+mod cancel;
+pub mod chat;
+mod chat_stream;
+mod continue_generation;
+mod get_llm_call;
+mod get_llm_calls;
+mod images;
+mod list_tools;
+mod pricing;
+pub mod retry;
+mod search_llm_calls;
+mod tools;
+mod usage;
 
+pub use cancel::{cancel_chat, ActiveChat};
 pub use chat::chat;
+pub use chat_stream::chat_stream;
+pub use continue_generation::continue_generation;
+pub use get_llm_call::get_llm_call;
+pub use get_llm_calls::get_llm_calls;
+pub use list_tools::list_tools;
+pub use search_llm_calls::search_llm_calls;
+pub use usage::get_usage_summary;
@@ -0,0 +1,167 @@
+use crate::commands::preferences::{ModelPricing, Preferences};
+use crate::models::llm_calls::{Llm, TokenMetadata};
+use crate::setup::api_keys::Service;
+
+const TOKENS_PER_PRICING_UNIT: f64 = 1_000_000.0;
+
+fn price(
+    provider: Service,
+    model: &str,
+    prompt_per_million: f64,
+    response_per_million: f64,
+) -> ModelPricing {
+    ModelPricing {
+        provider,
+        model: model.to_string(),
+        prompt_per_million,
+        response_per_million,
+    }
+}
+
+/// Published prices for the models we use, as of March 2024.
+pub fn default_pricing() -> Vec<ModelPricing> {
+    vec![
+        price(Service::OpenAI, "gpt-4", 30.0, 60.0),
+        price(Service::OpenAI, "gpt-4-32k", 60.0, 120.0),
+        price(Service::OpenAI, "gpt-4-turbo", 10.0, 30.0),
+        price(Service::OpenAI, "gpt-4-0125-preview", 10.0, 30.0),
+        price(Service::OpenAI, "gpt-4-1106-preview", 10.0, 30.0),
+        price(Service::OpenAI, "gpt-3.5-turbo", 0.5, 1.5),
+        price(Service::Anthropic, "claude-3-opus", 15.0, 75.0),
+        price(Service::Anthropic, "claude-3-sonnet", 3.0, 15.0),
+        price(Service::Anthropic, "claude-3-haiku", 0.25, 1.25),
+        // local models cost nothing per token, whichever one it is
+        price(Service::Ollama, "", 0.0, 0.0),
+    ]
+}
+
+/// Whether a suffix names a dated snapshot, such as the `0613` in `gpt-4-0613`, the
+/// `2024-04-09` in `gpt-4-turbo-2024-04-09` or the `20240229` in
+/// `claude-3-opus-20240229`.
+fn is_snapshot_date(suffix: &str) -> bool {
+    let digits = |part: &str, len: usize| {
+        part.len() == len && part.chars().all(|c| c.is_ascii_digit())
+    };
+    let parts: Vec<&str> = suffix.split('-').collect();
+    match parts.as_slice() {
+        [date] => digits(date, 4) || digits(date, 8),
+        [year, month, day] => digits(year, 4) && digits(month, 2) && digits(day, 2),
+        _ => false,
+    }
+}
+
+/// Matches a model to its own price or to that of the model it's a snapshot of.
+/// Other models sharing a prefix, such as `gpt-4o` for `gpt-4`, don't match. An
+/// empty model name matches every model of the provider.
+fn matches_model(pricing: &ModelPricing, model: &str) -> bool {
+    if pricing.model.is_empty() || pricing.model == model {
+        return true;
+    }
+    model
+        .strip_prefix(&pricing.model)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(is_snapshot_date)
+}
+
+fn best_match<'a>(
+    pricing: &'a [ModelPricing],
+    provider: &Service,
+    model: &str,
+) -> Option<&'a ModelPricing> {
+    pricing
+        .iter()
+        .filter(|p| &p.provider == provider && matches_model(p, model))
+        .max_by_key(|p| p.model.len())
+}
+
+/// Finds the price of a model, preferring the user's own pricing table over the
+/// built-in one.
+pub fn find_pricing(
+    preferences: &Preferences,
+    provider: &Service,
+    model: &str,
+) -> Option<ModelPricing> {
+    let user_pricing = preferences.pricing.as_deref().unwrap_or_default();
+    match best_match(user_pricing, provider, model) {
+        Some(pricing) => Some(pricing.clone()),
+        None => best_match(&default_pricing(), provider, model).cloned(),
+    }
+}
+
+/// Returns the cost of a call in US dollars, if both its token counts and its
+/// model's price are known.
+pub fn compute_cost(
+    preferences: &Preferences,
+    llm: &Llm,
+    tokens: &TokenMetadata,
+) -> Option<f64> {
+    let pricing = find_pricing(preferences, &llm.provider, &llm.name)?;
+    let prompt_tokens = tokens.prompt? as f64;
+    let response_tokens = tokens.response? as f64;
+    Some(
+        (prompt_tokens * pricing.prompt_per_million
+            + response_tokens * pricing.response_per_million)
+            / TOKENS_PER_PRICING_UNIT,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_priced_like_their_model() {
+        let find = |model: &str| {
+            find_pricing(&Preferences::default(), &Service::OpenAI, model)
+                .map(|pricing| pricing.model)
+        };
+        assert_eq!(find("gpt-4-32k-0613"), Some("gpt-4-32k".to_string()));
+        assert_eq!(
+            find("gpt-4-turbo-2024-04-09"),
+            Some("gpt-4-turbo".to_string())
+        );
+        let claude = find_pricing(
+            &Preferences::default(),
+            &Service::Anthropic,
+            "claude-3-opus-20240229",
+        );
+        assert_eq!(claude.unwrap().model, "claude-3-opus");
+    }
+
+    #[test]
+    fn test_other_models_with_same_prefix_unpriced() {
+        for model in ["gpt-4o", "gpt-4o-2024-05-13", "gpt-4-vision-preview"] {
+            assert!(
+                find_pricing(&Preferences::default(), &Service::OpenAI, model)
+                    .is_none(),
+                "{model} should not get another model's price"
+            );
+        }
+    }
+
+    #[test]
+    fn test_user_pricing_takes_precedence() {
+        let preferences = Preferences {
+            pricing: Some(vec![price(Service::OpenAI, "gpt-4", 1.0, 2.0)]),
+            ..Preferences::default()
+        };
+        let pricing =
+            find_pricing(&preferences, &Service::OpenAI, "gpt-4-0613").unwrap();
+        assert_eq!(pricing.prompt_per_million, 1.0);
+    }
+
+    #[test]
+    fn test_unknown_model_has_no_cost() {
+        let llm = Llm {
+            name: "mystery-model".to_string(),
+            requested: "mystery-model".to_string(),
+            provider: Service::OpenAI,
+        };
+        let tokens = TokenMetadata {
+            prompt: Some(10),
+            response: Some(10),
+            total: Some(20),
+        };
+        assert_eq!(compute_cost(&Preferences::default(), &llm, &tokens), None);
+    }
+}
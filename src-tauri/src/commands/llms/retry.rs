@@ -0,0 +1,403 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::preferences::Preferences;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use std::time::Duration;
+use task_local_extensions::Extensions;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+/// Covers the whole request, including the time spent streaming the response.
+const DEFAULT_TIMEOUT_SECONDS: u32 = 300;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Retries requests that were rate limited or that failed because of server or
+/// network problems, waiting longer after each failed attempt unless the server
+/// says how long to wait. If the server asks for a longer wait than `max_delay`,
+/// its response is returned as is instead.
+pub struct RetryMiddleware {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryMiddleware {
+    pub fn new(max_attempts: u32) -> Self {
+        RetryMiddleware {
+            max_attempts: max_attempts.max(1),
+            base_delay: BASE_DELAY,
+            max_delay: MAX_DELAY,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay)
+    }
+}
+
+fn timed_http_client(preferences: &Preferences) -> ZammResult<reqwest::Client> {
+    let timeout = preferences
+        .request_timeout_seconds
+        .unwrap_or(DEFAULT_TIMEOUT_SECONDS);
+    Ok(reqwest::ClientBuilder::new()
+        .timeout(Duration::from_secs(timeout.into()))
+        .build()?)
+}
+
+/// Builds the HTTP client used for LLM API calls, with the retry and timeout
+/// settings from the user's preferences.
+pub fn llm_http_client(preferences: &Preferences) -> ZammResult<ClientWithMiddleware> {
+    let max_attempts = preferences
+        .max_request_attempts
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    Ok(ClientBuilder::new(timed_http_client(preferences)?)
+        .with(RetryMiddleware::new(max_attempts))
+        .build())
+}
+
+/// Builds the HTTP client used to check API keys. The user is waiting on the answer,
+/// so nothing gets retried.
+pub fn validation_http_client(
+    preferences: &Preferences,
+) -> ZammResult<ClientWithMiddleware> {
+    Ok(ClientBuilder::new(timed_http_client(preferences)?).build())
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_retryable_error(error: &reqwest_middleware::Error) -> bool {
+    match error {
+        reqwest_middleware::Error::Reqwest(e) => e.is_timeout() || e.is_connect(),
+        reqwest_middleware::Error::Middleware(_) => false,
+    }
+}
+
+fn seconds(value: f64) -> Option<Duration> {
+    if value.is_finite() && value >= 0.0 {
+        Some(Duration::from_secs_f64(value))
+    } else {
+        None
+    }
+}
+
+/// Parses durations such as `1s`, `6m0s`, `615ms` or `17.271s`, as found in the
+/// `x-ratelimit-reset-*` headers.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let unit_seconds = match c {
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += number.parse::<f64>().ok()? * unit_seconds;
+        number.clear();
+    }
+    if !number.is_empty() || value.trim().is_empty() {
+        return None;
+    }
+    seconds(total)
+}
+
+/// How long the server asked us to wait before trying again, if it said at all.
+fn requested_delay(response: &Response) -> Option<Duration> {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return seconds(ms / 1000.0);
+    }
+    if let Some(retry_after) = header("retry-after") {
+        if let Ok(secs) = retry_after.parse::<f64>() {
+            return seconds(secs);
+        }
+        // the header may also be an HTTP date instead
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(retry_after) {
+            let wait = date.signed_duration_since(chrono::Utc::now());
+            return Some(wait.to_std().unwrap_or(Duration::ZERO));
+        }
+    }
+    // otherwise, wait for whichever limit has run out to reset
+    ["requests", "tokens"]
+        .iter()
+        .filter(|limit| header(&format!("x-ratelimit-remaining-{limit}")) == Some("0"))
+        .filter_map(|limit| {
+            header(&format!("x-ratelimit-reset-{limit}")).and_then(parse_reset_duration)
+        })
+        .max()
+}
+
+fn describe(result: &reqwest_middleware::Result<Response>) -> String {
+    match result {
+        Ok(response) => response.status().to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let description = format!("{} {}", req.method(), req.url());
+        let mut attempt = 1;
+        loop {
+            // requests with streaming bodies can't be replayed, so they only get
+            // the one attempt
+            let Some(attempt_req) = req.try_clone() else {
+                return next.run(req, extensions).await;
+            };
+            let result = next.clone().run(attempt_req, extensions).await;
+            let delay = match &result {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    Some(requested_delay(response).unwrap_or(self.backoff(attempt)))
+                }
+                Err(e) if is_retryable_error(e) => Some(self.backoff(attempt)),
+                _ => {
+                    if attempt > 1 {
+                        println!(
+                            "{description} attempt {attempt}/{}: {}",
+                            self.max_attempts,
+                            describe(&result)
+                        );
+                    }
+                    return result;
+                }
+            };
+
+            let outcome = describe(&result);
+            match delay {
+                Some(delay) if delay > self.max_delay => {
+                    eprintln!(
+                        "{description} attempt {attempt}/{}: {outcome}, giving up \
+                         because the server asked to wait {delay:?}",
+                        self.max_attempts
+                    );
+                    return result;
+                }
+                Some(delay) if attempt < self.max_attempts => {
+                    eprintln!(
+                        "{description} attempt {attempt}/{}: {outcome}, retrying in \
+                         {delay:?}",
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => {
+                    eprintln!(
+                        "{description} attempt {attempt}/{}: {outcome}, giving up",
+                        self.max_attempts
+                    );
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::chat_helper;
+    use crate::commands::llms::chat::tests::setup_vcr_middleware;
+    use crate::models::llm_calls::{ChatMessage, LlmCall};
+    use crate::setup::api_keys::Service;
+    use crate::test_helpers::{setup_blob_store, setup_zamm_db};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the attempts that make it past the retry middleware.
+    struct CountAttempts(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl Middleware for CountAttempts {
+        async fn handle(
+            &self,
+            req: Request,
+            extensions: &mut Extensions,
+            next: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            next.run(req, extensions).await
+        }
+    }
+
+    /// The first response in this recording is a rate limit error, and the second
+    /// is the successful reply to the retried request. The rate limit error was
+    /// written by hand, so the formats of OpenAI's own headers are checked
+    /// separately.
+    async fn rate_limited_chat(retry: RetryMiddleware) -> (ZammResult<LlmCall>, usize) {
+        let (api_keys, vcr_middleware) =
+            setup_vcr_middleware("api/sample-call-requests/rate-limited.json");
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(retry)
+            .with(CountAttempts(attempts.clone()))
+            .with(vcr_middleware)
+            .build();
+        let result = chat_helper(
+            &api_keys,
+            &setup_zamm_db(),
+            &setup_blob_store(),
+            &Preferences::default(),
+            Service::OpenAI,
+            "gpt-4".to_string(),
+            None,
+            vec![
+                ChatMessage::System {
+                    text: "You are ZAMM, a chat program. Respond in first person."
+                        .to_string(),
+                },
+                ChatMessage::Human {
+                    text: "Hello, does this work?".to_string(),
+                    images: vec![],
+                },
+            ],
+            None,
+            None,
+            None,
+            client,
+        )
+        .await;
+        (result, attempts.load(Ordering::SeqCst))
+    }
+
+    fn response_with_headers(headers: &[(&str, &str)]) -> Response {
+        let mut response =
+            http::Response::builder().status(StatusCode::TOO_MANY_REQUESTS);
+        for (name, value) in headers {
+            response = response.header(*name, *value);
+        }
+        Response::from(response.body("").unwrap())
+    }
+
+    #[test]
+    fn test_parse_reset_duration() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("615ms"),
+            Some(Duration::from_millis(615))
+        );
+        assert_eq!(
+            parse_reset_duration("1h2m17.5s"),
+            Some(Duration::from_secs_f64(3737.5))
+        );
+        assert_eq!(parse_reset_duration("0s"), Some(Duration::ZERO));
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration("12"), None);
+    }
+
+    #[test]
+    fn test_openai_rate_limit_headers() {
+        // headers as sent along with OpenAI's rate limit errors
+        let response = response_with_headers(&[
+            ("x-ratelimit-limit-requests", "10000"),
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "6m0s"),
+            ("x-ratelimit-limit-tokens", "10000"),
+            ("x-ratelimit-remaining-tokens", "9897"),
+            ("x-ratelimit-reset-tokens", "615ms"),
+        ]);
+        // only the limit that has run out matters
+        assert_eq!(requested_delay(&response), Some(Duration::from_secs(360)));
+
+        let response = response_with_headers(&[
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "17.271s"),
+            ("x-ratelimit-remaining-tokens", "0"),
+            ("x-ratelimit-reset-tokens", "1m2.5s"),
+        ]);
+        assert_eq!(
+            requested_delay(&response),
+            Some(Duration::from_secs_f64(62.5))
+        );
+    }
+
+    #[test]
+    fn test_retry_after_headers() {
+        let response = response_with_headers(&[
+            ("retry-after", "20"),
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "6m0s"),
+        ]);
+        assert_eq!(requested_delay(&response), Some(Duration::from_secs(20)));
+
+        let response =
+            response_with_headers(&[("retry-after-ms", "1500"), ("retry-after", "2")]);
+        assert_eq!(
+            requested_delay(&response),
+            Some(Duration::from_millis(1500))
+        );
+
+        // a date in the past means there's no need to wait any longer
+        let response =
+            response_with_headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        assert_eq!(requested_delay(&response), Some(Duration::ZERO));
+
+        let response = response_with_headers(&[("retry-after", "whenever")]);
+        assert_eq!(requested_delay(&response), None);
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let middleware = RetryMiddleware::new(10);
+        assert_eq!(middleware.backoff(1), Duration::from_millis(500));
+        assert_eq!(middleware.backoff(3), Duration::from_secs(2));
+        assert_eq!(middleware.backoff(10), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_retried() {
+        let (result, attempts) = rate_limited_chat(RetryMiddleware::new(3)).await;
+        let llm_call = result.unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(
+            llm_call.response.completion,
+            ChatMessage::AI {
+                text: "Yes, it works. How can I assist you today?".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_long_requested_delay_not_cut_short() {
+        // the server asks for a wait of 6ms here
+        let impatient = RetryMiddleware {
+            max_delay: Duration::from_millis(1),
+            ..RetryMiddleware::new(3)
+        };
+        let (result, attempts) = rate_limited_chat(impatient).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_gives_up() {
+        let (result, attempts) = rate_limited_chat(RetryMiddleware::new(1)).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
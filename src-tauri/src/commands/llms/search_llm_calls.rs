@@ -0,0 +1,163 @@
+use crate::commands::errors::ZammResult;
+use crate::models::llm_calls::{LlmCall, LlmCallRow};
+use crate::ZammDatabase;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::State;
+
+const DEFAULT_MAX_RESULTS: i64 = 50;
+const HIGHLIGHT_START: &str = "<mark>";
+const HIGHLIGHT_END: &str = "</mark>";
+const SNIPPET_ELLIPSIS: &str = "…";
+const SNIPPET_TOKENS: i32 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+pub struct LlmCallSearchResult {
+    pub llm_call: LlmCall,
+    /// Excerpt of the best-matching prompt or completion text, with matching terms
+    /// wrapped in `<mark>` tags.
+    pub snippet: String,
+}
+
+#[derive(QueryableByName)]
+struct SearchResultRow {
+    #[diesel(embed)]
+    llm_call: LlmCallRow,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+}
+
+/// Turns free-form user input into an FTS5 query that matches calls containing
+/// every word, so that punctuation in the input can't be mistaken for query syntax.
+fn to_fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+async fn search_llm_calls_helper(
+    zamm_db: &ZammDatabase,
+    query: &str,
+    limit: Option<i64>,
+) -> ZammResult<Vec<LlmCallSearchResult>> {
+    let fts_query = to_fts_query(query);
+    if fts_query.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let conn = &mut *zamm_db.connection()?;
+
+    let sql = format!(
+        "SELECT llm_calls.*, snippet(llm_calls_fts, -1, '{HIGHLIGHT_START}', \
+         '{HIGHLIGHT_END}', '{SNIPPET_ELLIPSIS}', {SNIPPET_TOKENS}) AS snippet \
+         FROM llm_calls_fts JOIN llm_calls ON llm_calls.id = llm_calls_fts.id \
+         WHERE llm_calls_fts MATCH ? ORDER BY rank LIMIT ?"
+    );
+    let results = diesel::sql_query(sql)
+        .bind::<Text, _>(fts_query)
+        .bind::<BigInt, _>(limit.unwrap_or(DEFAULT_MAX_RESULTS))
+        .load::<SearchResultRow>(conn)?
+        .into_iter()
+        .map(|row| LlmCallSearchResult {
+            llm_call: row.llm_call.into(),
+            snippet: row.snippet,
+        })
+        .collect();
+    Ok(results)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn search_llm_calls(
+    database: State<'_, ZammDatabase>,
+    query: String,
+    limit: Option<i64>,
+) -> ZammResult<Vec<LlmCallSearchResult>> {
+    search_llm_calls_helper(&database, &query, limit).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::test_helpers::setup_zamm_db;
+
+    async fn setup_history() -> (ZammDatabase, Vec<LlmCall>) {
+        let db = setup_zamm_db();
+        let mut llm_calls = vec![];
+        for (sample, timestamp) in [
+            ("chat-start-conversation", "2024-03-01T10:00:00"),
+            ("chat-continue-conversation", "2024-03-01T11:00:00"),
+        ] {
+            let llm_call = read_sample_llm_call(
+                &format!("api/sample-calls/{sample}.yaml"),
+                timestamp,
+            );
+            insert_llm_call(&db, &llm_call).await;
+            llm_calls.push(llm_call);
+        }
+        (db, llm_calls)
+    }
+
+    #[test]
+    fn test_fts_query_escaping() {
+        assert_eq!(
+            to_fts_query("  say \"hi\" OR"),
+            "\"say\" \"\"\"hi\"\"\" \"OR\""
+        );
+        assert_eq!(to_fts_query("   "), "");
+    }
+
+    #[tokio::test]
+    async fn test_search_completion() {
+        let (db, inserted) = setup_history().await;
+        let results = search_llm_calls_helper(&db, "funny", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].llm_call.id, inserted[1].id);
+        assert!(results[0].snippet.contains("<mark>funny</mark>"));
+    }
+
+    #[tokio::test]
+    async fn test_search_matches_all_words() {
+        let (db, _) = setup_history().await;
+        let results = search_llm_calls_helper(&db, "ZAMM chat", None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+
+        let results = search_llm_calls_helper(&db, "ZAMM penguin", None)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_alternate_choices_and_tool_calls() {
+        let db = setup_zamm_db();
+        let mut inserted = vec![];
+        for sample in ["chat-multiple-choices", "chat-tool-calls"] {
+            let llm_call = read_sample_llm_call(
+                &format!("api/sample-calls/{sample}.yaml"),
+                "2024-03-01T10:00:00",
+            );
+            insert_llm_call(&db, &llm_call).await;
+            inserted.push(llm_call);
+        }
+
+        // only the second choice suggests this name
+        let results = search_llm_calls_helper(&db, "Mochi", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].llm_call.id, inserted[0].id);
+
+        // the name of the tool only appears in the model's request to run it
+        let results = search_llm_calls_helper(&db, "count_characters", None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].llm_call.id, inserted[1].id);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use crate::models::llm_calls::{ChatMessage, ToolCall, ToolNames};
+use async_openai::types::{ChatCompletionTool, ChatCompletionToolType, FunctionObject};
+use serde::Deserialize;
+use serde_json::json;
+
+/// A tool that runs locally, and that the model can ask to have run on its behalf.
+pub trait LocalTool: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// JSON schema for the arguments that the tool takes.
+    fn parameters(&self) -> serde_json::Value;
+    fn call(&self, arguments: serde_json::Value) -> ZammResult<String>;
+}
+
+struct CurrentTime;
+
+impl LocalTool for CurrentTime {
+    fn name(&self) -> &'static str {
+        "current_time"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current date and time on the user's computer."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({"type": "object", "properties": {}})
+    }
+
+    fn call(&self, _arguments: serde_json::Value) -> ZammResult<String> {
+        Ok(chrono::Local::now().to_rfc3339())
+    }
+}
+
+#[derive(Deserialize)]
+struct CountCharactersArgs {
+    text: String,
+    character: String,
+}
+
+struct CountCharacters;
+
+impl LocalTool for CountCharacters {
+    fn name(&self) -> &'static str {
+        "count_characters"
+    }
+
+    fn description(&self) -> &'static str {
+        "Count how many times a character appears in some text."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {"type": "string", "description": "The text to search"},
+                "character": {
+                    "type": "string",
+                    "description": "The character to count"
+                }
+            },
+            "required": ["text", "character"]
+        })
+    }
+
+    fn call(&self, arguments: serde_json::Value) -> ZammResult<String> {
+        let args: CountCharactersArgs = serde_json::from_value(arguments)?;
+        let mut characters = args.character.chars();
+        let character = match (characters.next(), characters.next()) {
+            (Some(c), None) => c,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Expected a single character, got \"{}\"",
+                    args.character
+                )
+                .into())
+            }
+        };
+        Ok(args.text.matches(character).count().to_string())
+    }
+}
+
+static TOOL_REGISTRY: &[&dyn LocalTool] = &[&CurrentTime, &CountCharacters];
+
+pub fn available_tools() -> &'static [&'static dyn LocalTool] {
+    TOOL_REGISTRY
+}
+
+/// Looks up the tools enabled for a chat, erroring out on any unknown names.
+pub fn get_tools(names: Option<&ToolNames>) -> ZammResult<Vec<&'static dyn LocalTool>> {
+    names
+        .map(|names| names.0.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|name| {
+            TOOL_REGISTRY
+                .iter()
+                .find(|tool| tool.name() == name.as_str())
+                .copied()
+                .ok_or(Error::UnknownTool { name: name.clone() })
+        })
+        .collect()
+}
+
+pub fn openai_tools(tools: &[&dyn LocalTool]) -> Vec<ChatCompletionTool> {
+    tools
+        .iter()
+        .map(|tool| ChatCompletionTool {
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionObject {
+                name: tool.name().to_string(),
+                description: Some(tool.description().to_string()),
+                parameters: Some(tool.parameters()),
+            },
+        })
+        .collect()
+}
+
+/// Runs the tool that the model asked for. Failures are reported back to the
+/// model as the tool's output, so that it gets a chance to correct itself.
+pub fn run_tool_call(tools: &[&dyn LocalTool], tool_call: &ToolCall) -> ChatMessage {
+    let output = tools
+        .iter()
+        .find(|tool| tool.name() == tool_call.name)
+        .ok_or(Error::UnknownTool {
+            name: tool_call.name.clone(),
+        })
+        .and_then(|tool| {
+            let arguments = serde_json::from_str(&tool_call.arguments)?;
+            tool.call(arguments)
+        });
+    ChatMessage::Tool {
+        tool_call_id: tool_call.id.clone(),
+        text: match output {
+            Ok(text) => text,
+            Err(e) => format!("Error: {e}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_call(arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: "count_characters".to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_count_characters() {
+        let tools =
+            get_tools(Some(&ToolNames(vec!["count_characters".to_string()]))).unwrap();
+        assert_eq!(
+            run_tool_call(
+                &tools,
+                &count_call(r#"{"text": "strawberry", "character": "r"}"#)
+            ),
+            ChatMessage::Tool {
+                tool_call_id: "call_1".to_string(),
+                text: "3".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tool_errors_reported_to_model() {
+        let tools =
+            get_tools(Some(&ToolNames(vec!["count_characters".to_string()]))).unwrap();
+        let ChatMessage::Tool { text, .. } =
+            run_tool_call(&tools, &count_call(r#"{"text": "strawberry"}"#))
+        else {
+            panic!("Unexpected message type");
+        };
+        assert!(text.starts_with("Error: "), "Unexpected output: {text}");
+
+        // tools that weren't enabled can't be run either
+        let ChatMessage::Tool { text, .. } = run_tool_call(
+            &[],
+            &count_call(r#"{"text": "strawberry", "character": "r"}"#),
+        ) else {
+            panic!("Unexpected message type");
+        };
+        assert_eq!(text, "Error: No tool named count_characters");
+    }
+
+    #[test]
+    fn test_unknown_tool_rejected() {
+        let result = get_tools(Some(&ToolNames(vec!["rm_rf".to_string()])));
+        assert!(matches!(result, Err(Error::UnknownTool { .. })));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::Service;
+use crate::ZammDatabase;
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Double, Nullable, Text, Timestamp};
+use serde::{Deserialize, Serialize};
+use specta::specta;
+use tauri::State;
+
+/// Usage of a single model on a single day. Totals by day, model or provider can
+/// be obtained by adding these up.
+#[derive(
+    Debug, Clone, PartialEq, Serialize, Deserialize, QueryableByName, specta::Type,
+)]
+pub struct UsageSummaryEntry {
+    #[diesel(sql_type = Date)]
+    pub date: NaiveDate,
+    #[diesel(sql_type = Text)]
+    pub provider: Service,
+    #[diesel(sql_type = Text)]
+    pub llm: String,
+    #[diesel(sql_type = BigInt)]
+    pub num_calls: i64,
+    #[diesel(sql_type = BigInt)]
+    pub prompt_tokens: i64,
+    #[diesel(sql_type = BigInt)]
+    pub response_tokens: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total_tokens: i64,
+    /// Spend in US dollars, counting only calls whose cost is known.
+    #[diesel(sql_type = Double)]
+    pub cost: f64,
+}
+
+const USAGE_SUMMARY_QUERY: &str = "\
+    SELECT date(timestamp) AS date, provider, llm, \
+        COUNT(*) AS num_calls, \
+        COALESCE(SUM(prompt_tokens), 0) AS prompt_tokens, \
+        COALESCE(SUM(response_tokens), 0) AS response_tokens, \
+        COALESCE(SUM(total_tokens), 0) AS total_tokens, \
+        COALESCE(SUM(cost), 0.0) AS cost \
+    FROM llm_calls \
+    WHERE (?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR timestamp <= ?2) \
+    GROUP BY date(timestamp), provider, llm \
+    ORDER BY date(timestamp) DESC, provider, llm";
+
+async fn get_usage_summary_helper(
+    zamm_db: &ZammDatabase,
+    start_time: Option<NaiveDateTime>,
+    end_time: Option<NaiveDateTime>,
+) -> ZammResult<Vec<UsageSummaryEntry>> {
+    let conn = &mut *zamm_db.connection()?;
+
+    let entries = diesel::sql_query(USAGE_SUMMARY_QUERY)
+        .bind::<Nullable<Timestamp>, _>(start_time)
+        .bind::<Nullable<Timestamp>, _>(end_time)
+        .load::<UsageSummaryEntry>(conn)?;
+    Ok(entries)
+}
+
+#[tauri::command(async)]
+#[specta]
+pub async fn get_usage_summary(
+    database: State<'_, ZammDatabase>,
+    start_time: Option<NaiveDateTime>,
+    end_time: Option<NaiveDateTime>,
+) -> ZammResult<Vec<UsageSummaryEntry>> {
+    get_usage_summary_helper(&database, start_time, end_time).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::llms::chat::tests::{insert_llm_call, read_sample_llm_call};
+    use crate::models::llm_calls::LlmCall;
+    use crate::test_helpers::setup_zamm_db;
+
+    async fn insert_sample(
+        db: &ZammDatabase,
+        sample: &str,
+        timestamp: &str,
+        cost: Option<f64>,
+    ) {
+        let llm_call = LlmCall {
+            cost,
+            ..read_sample_llm_call(
+                &format!("api/sample-calls/{sample}.yaml"),
+                timestamp,
+            )
+        };
+        insert_llm_call(db, &llm_call).await;
+    }
+
+    #[tokio::test]
+    async fn test_usage_grouped_by_day_and_model() {
+        let db = setup_zamm_db();
+        insert_sample(
+            &db,
+            "chat-start-conversation",
+            "2024-03-01T10:00:00",
+            Some(0.5),
+        )
+        .await;
+        insert_sample(
+            &db,
+            "chat-continue-conversation",
+            "2024-03-01T11:00:00",
+            Some(0.25),
+        )
+        .await;
+        insert_sample(&db, "chat-start-conversation", "2024-03-02T10:00:00", None)
+            .await;
+        insert_sample(
+            &db,
+            "chat-anthropic-start-conversation",
+            "2024-03-02T11:00:00",
+            Some(0.125),
+        )
+        .await;
+
+        let summary = get_usage_summary_helper(&db, None, None).await.unwrap();
+        let day = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        assert_eq!(
+            summary,
+            vec![
+                UsageSummaryEntry {
+                    date: day("2024-03-02"),
+                    provider: Service::Anthropic,
+                    llm: "claude-3-haiku-20240307".to_string(),
+                    num_calls: 1,
+                    prompt_tokens: 27,
+                    response_tokens: 24,
+                    total_tokens: 51,
+                    cost: 0.125,
+                },
+                UsageSummaryEntry {
+                    date: day("2024-03-02"),
+                    provider: Service::OpenAI,
+                    llm: "gpt-4-0613".to_string(),
+                    num_calls: 1,
+                    prompt_tokens: 32,
+                    response_tokens: 12,
+                    total_tokens: 44,
+                    cost: 0.0,
+                },
+                UsageSummaryEntry {
+                    date: day("2024-03-01"),
+                    provider: Service::OpenAI,
+                    llm: "gpt-4-0613".to_string(),
+                    num_calls: 2,
+                    prompt_tokens: 89,
+                    response_tokens: 34,
+                    total_tokens: 123,
+                    cost: 0.75,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage_within_time_range() {
+        let db = setup_zamm_db();
+        insert_sample(&db, "chat-start-conversation", "2024-03-01T10:00:00", None)
+            .await;
+        insert_sample(&db, "chat-start-conversation", "2024-03-02T10:00:00", None)
+            .await;
+
+        let start_time =
+            NaiveDateTime::parse_from_str("2024-03-02T00:00:00", "%Y-%m-%dT%H:%M:%S")
+                .unwrap();
+        let summary = get_usage_summary_helper(&db, Some(start_time), None)
+            .await
+            .unwrap();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].num_calls, 1);
+    }
+}
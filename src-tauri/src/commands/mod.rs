@@ -1,13 +1,28 @@
-mod errors;
+mod conversations;
+mod database;
+pub mod errors;
 mod keys;
 mod llms;
-mod preferences;
+pub mod preferences;
 mod sounds;
 mod system;
 
+pub use conversations::{
+    create_conversation, delete_conversation, get_conversation, list_conversations,
+    rename_conversation,
+};
+pub use database::{
+    backup_database, get_database_status, reconnect_database, restore_database,
+    switch_database,
+};
 pub use errors::Error;
-pub use keys::{get_api_keys, set_api_key};
-pub use llms::chat;
+pub use keys::{
+    get_api_keys, reveal_api_key, set_api_key, unlock_api_keys, validate_api_key,
+};
+pub use llms::{
+    cancel_chat, chat, chat_stream, continue_generation, get_llm_call, get_llm_calls,
+    get_usage_summary, list_tools, search_llm_calls, ActiveChat,
+};
 pub use preferences::{get_preferences, set_preferences};
 pub use sounds::play_sound;
 pub use system::get_system_info;
@@ -2,6 +2,6 @@ mod models;
 mod read;
 mod write;
 
-pub use models::Preferences;
-pub use read::get_preferences;
-pub use write::set_preferences;
+pub use models::{Budget, BudgetPeriod, ModelPricing, Preferences};
+pub use read::{get_preferences, get_preferences_helper};
+pub use write::{set_preferences, set_preferences_helper};
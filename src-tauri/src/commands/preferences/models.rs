@@ -1,19 +1,60 @@
 use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::Service;
 use anyhow::anyhow;
 use path_absolutize::Absolutize;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::path::PathBuf;
+use strum_macros::Display;
 
 pub static PREFERENCES_FILENAME: &str = "preferences.toml";
 
+/// Price of a model in US dollars per million tokens. An entry for `gpt-4` covers
+/// that exact model and its dated snapshots such as `gpt-4-0613`, but not other
+/// models such as `gpt-4o`. An empty model name covers every model of the provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct ModelPricing {
+    pub provider: Service,
+    pub model: String,
+    pub prompt_per_million: f64,
+    pub response_per_million: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+/// Cap on how much a provider may be used within each calendar day or month (UTC).
+/// Either limit, or both, may be set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct Budget {
+    pub provider: Service,
+    pub period: BudgetPeriod,
+    /// Maximum spend in US dollars.
+    pub max_cost: Option<f64>,
+    pub max_tokens: Option<i64>,
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Type)]
 pub struct Preferences {
-    animations_on: Option<bool>,
-    background_animation: Option<bool>,
-    animation_speed: Option<f64>,
-    sound_on: Option<bool>,
-    volume: Option<f64>,
+    pub animations_on: Option<bool>,
+    pub background_animation: Option<bool>,
+    pub animation_speed: Option<f64>,
+    pub sound_on: Option<bool>,
+    pub volume: Option<f64>,
+    pub ollama_base_url: Option<String>,
+    /// Overrides for the built-in pricing table.
+    pub pricing: Option<Vec<ModelPricing>>,
+    pub budgets: Option<Vec<Budget>>,
+    /// How many times to try an LLM request before giving up, including the first.
+    pub max_request_attempts: Option<u32>,
+    pub request_timeout_seconds: Option<u32>,
+    /// Database file to use instead of the one in the user data dir. The
+    /// `ZAMM_DB_PATH` environment variable takes precedence over this.
+    pub database_path: Option<String>,
 }
 
 pub fn get_preferences_file(
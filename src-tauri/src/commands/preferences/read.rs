@@ -22,7 +22,7 @@ fn get_preferences_happy_path(
     }
 }
 
-fn get_preferences_helper(preferences_path: &Option<PathBuf>) -> Preferences {
+pub fn get_preferences_helper(preferences_path: &Option<PathBuf>) -> Preferences {
     match get_preferences_happy_path(preferences_path) {
         Ok(preferences) => preferences,
         Err(e) => {
@@ -1,18 +1,21 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use diesel::sqlite::SqliteConnection;
-
 use setup::api_keys::{setup_api_keys, ApiKeys};
+use setup::db::DatabaseState;
+use setup::secrets::SecretStore;
 #[cfg(debug_assertions)]
 use specta::collect_types;
 
 #[cfg(debug_assertions)]
 use tauri_specta::ts;
 
+use std::collections::HashMap;
 use std::env;
+use tauri::Manager;
 
 use tokio::sync::Mutex;
+use uuid::Uuid;
 mod commands;
 mod models;
 #[cfg(test)]
@@ -21,44 +24,105 @@ mod schema;
 mod setup;
 #[cfg(test)]
 mod test_helpers;
+use commands::preferences::get_preferences_helper;
 use commands::{
-    chat, get_api_keys, get_preferences, get_system_info, play_sound, set_api_key,
-    set_preferences,
+    backup_database, cancel_chat, chat, chat_stream, continue_generation,
+    create_conversation, delete_conversation, get_api_keys, get_conversation,
+    get_database_status, get_llm_call, get_llm_calls, get_preferences, get_system_info,
+    get_usage_summary, list_conversations, list_tools, play_sound, reconnect_database,
+    rename_conversation, restore_database, reveal_api_key, search_llm_calls,
+    set_api_key, set_preferences, switch_database, unlock_api_keys, validate_api_key,
+    ActiveChat,
 };
 
-pub struct ZammDatabase(Mutex<Option<SqliteConnection>>);
+pub struct ZammDatabase(std::sync::RwLock<DatabaseState>);
 pub struct ZammApiKeys(Mutex<ApiKeys>);
+/// Where API keys get saved to, so that they don't sit in the database.
+pub struct ZammSecrets(Box<dyn SecretStore>);
+/// Chat requests that are still in flight, by the ID they can be cancelled with.
+pub struct ZammActiveChats(Mutex<HashMap<Uuid, ActiveChat>>);
 
 fn main() {
     #[cfg(debug_assertions)]
     ts::export(
         collect_types![
             get_api_keys,
+            reveal_api_key,
             set_api_key,
+            unlock_api_keys,
+            validate_api_key,
             play_sound,
             get_preferences,
             set_preferences,
             get_system_info,
-            chat
+            chat,
+            chat_stream,
+            cancel_chat,
+            continue_generation,
+            create_conversation,
+            list_conversations,
+            rename_conversation,
+            get_conversation,
+            delete_conversation,
+            get_llm_calls,
+            get_llm_call,
+            search_llm_calls,
+            get_usage_summary,
+            list_tools,
+            get_database_status,
+            reconnect_database,
+            switch_database,
+            backup_database,
+            restore_database
         ],
         "../src-svelte/src/lib/bindings.ts",
     )
     .unwrap();
 
-    let mut possible_db = setup::get_db();
-    let api_keys = setup_api_keys(&mut possible_db);
-
     tauri::Builder::default()
-        .manage(ZammDatabase(Mutex::new(possible_db)))
-        .manage(ZammApiKeys(Mutex::new(api_keys)))
+        .setup(|app| {
+            // the database location can be set in preferences, which are only
+            // found through the app
+            let preferences =
+                get_preferences_helper(&app.path_resolver().app_config_dir());
+            let zamm_db = setup::get_db(&preferences);
+            let secrets = setup::get_secret_store();
+            let api_keys = setup_api_keys(&zamm_db, secrets.as_ref());
+            app.manage(zamm_db);
+            app.manage(ZammApiKeys(Mutex::new(api_keys)));
+            app.manage(ZammSecrets(secrets));
+            Ok(())
+        })
+        .manage(ZammActiveChats(Mutex::new(HashMap::new())))
         .invoke_handler(tauri::generate_handler![
             get_api_keys,
+            reveal_api_key,
             set_api_key,
+            unlock_api_keys,
+            validate_api_key,
             play_sound,
             get_preferences,
             set_preferences,
             get_system_info,
-            chat
+            chat,
+            chat_stream,
+            cancel_chat,
+            continue_generation,
+            create_conversation,
+            list_conversations,
+            rename_conversation,
+            get_conversation,
+            delete_conversation,
+            get_llm_calls,
+            get_llm_call,
+            search_llm_calls,
+            get_usage_summary,
+            list_tools,
+            get_database_status,
+            reconnect_database,
+            switch_database,
+            backup_database,
+            restore_database
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
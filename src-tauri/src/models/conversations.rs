@@ -0,0 +1,35 @@
+use crate::models::llm_calls::EntityId;
+use crate::schema::conversations;
+use chrono::naive::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize, Deserialize, specta::Type)]
+#[diesel(table_name = conversations)]
+pub struct Conversation {
+    #[serde(flatten)]
+    pub id: EntityId,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = conversations)]
+pub struct NewConversation<'a> {
+    pub id: &'a EntityId,
+    pub title: &'a str,
+    pub created_at: &'a NaiveDateTime,
+    pub updated_at: &'a NaiveDateTime,
+}
+
+impl Conversation {
+    pub fn as_sql_row(&self) -> NewConversation {
+        NewConversation {
+            id: &self.id,
+            title: &self.title,
+            created_at: &self.created_at,
+            updated_at: &self.updated_at,
+        }
+    }
+}
@@ -2,9 +2,14 @@ use crate::commands::Error;
 use crate::schema::llm_calls;
 use crate::setup::api_keys::Service;
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
-    ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
-    ChatCompletionRequestUserMessageContent, ChatCompletionResponseMessage, Role,
+    ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessage,
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPart,
+    ChatCompletionRequestMessageContentPartImageArgs,
+    ChatCompletionRequestMessageContentPartTextArgs,
+    ChatCompletionRequestSystemMessage, ChatCompletionRequestToolMessage,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+    ChatCompletionResponseMessage, ChatCompletionToolType, FunctionCall, ImageUrlArgs,
+    Role,
 };
 use chrono::naive::NaiveDateTime;
 use diesel::backend::Backend;
@@ -18,10 +23,20 @@ use diesel::sqlite::Sqlite;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::ops::Deref;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
 use uuid::Uuid;
 
 #[derive(
-    AsExpression, FromSqlRow, Debug, Serialize, Deserialize, Clone, specta::Type,
+    AsExpression,
+    FromSqlRow,
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Eq,
+    specta::Type,
 )]
 #[diesel(sql_type = Text)]
 pub struct EntityId {
@@ -60,6 +75,75 @@ where
     }
 }
 
+/// An image attached to a chat message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, specta::Type)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum ChatImage {
+    /// An image file on the local filesystem.
+    Path { path: String },
+    /// Image data encoded in base64.
+    Base64 { media_type: String, data: String },
+    /// An image saved in the blob store under the SHA-256 hash of its contents.
+    Blob { media_type: String, hash: String },
+}
+
+impl ChatImage {
+    fn data_url(&self) -> Result<String, Error> {
+        match self {
+            ChatImage::Base64 { media_type, data } => {
+                Ok(format!("data:{media_type};base64,{data}"))
+            }
+            _ => Err(Error::InvalidImage {
+                reason: "Image data must be loaded before it can be sent".to_string(),
+            }),
+        }
+    }
+
+    fn from_data_url(url: &str) -> Result<Self, Error> {
+        url.strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+            .map(|(media_type, data)| ChatImage::Base64 {
+                media_type: media_type.to_string(),
+                data: data.to_string(),
+            })
+            .ok_or(Error::InvalidImage {
+                reason: "Only base64 data URLs are supported".to_string(),
+            })
+    }
+}
+
+/// A request from the model to run one of the tools that it was offered.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, specta::Type)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// JSON-encoded arguments, exactly as generated by the model.
+    pub arguments: String,
+}
+
+impl From<ChatCompletionMessageToolCall> for ToolCall {
+    fn from(tool_call: ChatCompletionMessageToolCall) -> Self {
+        ToolCall {
+            id: tool_call.id,
+            name: tool_call.function.name,
+            arguments: tool_call.function.arguments,
+        }
+    }
+}
+
+impl From<ToolCall> for ChatCompletionMessageToolCall {
+    fn from(tool_call: ToolCall) -> Self {
+        ChatCompletionMessageToolCall {
+            id: tool_call.id,
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: tool_call.name,
+                arguments: tool_call.arguments,
+            },
+        }
+    }
+}
+
 #[derive(
     Debug,
     Clone,
@@ -73,9 +157,26 @@ where
 #[diesel(sql_type = Text)]
 #[serde(tag = "role")]
 pub enum ChatMessage {
-    System { text: String },
-    Human { text: String },
-    AI { text: String },
+    System {
+        text: String,
+    },
+    Human {
+        text: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<ChatImage>,
+    },
+    AI {
+        text: String,
+    },
+    /// The model asking for tools to be run instead of replying with text.
+    AIToolCalls {
+        tool_calls: Vec<ToolCall>,
+    },
+    /// The output of a tool that the model asked to run.
+    Tool {
+        tool_call_id: String,
+        text: String,
+    },
 }
 
 impl TryFrom<ChatCompletionRequestMessage> for ChatMessage {
@@ -91,25 +192,56 @@ impl TryFrom<ChatCompletionRequestMessage> for ChatMessage {
             ChatCompletionRequestMessage::User(user_message) => {
                 match user_message.content {
                     ChatCompletionRequestUserMessageContent::Text(text) => {
-                        Ok(ChatMessage::Human { text })
+                        Ok(ChatMessage::Human {
+                            text,
+                            images: vec![],
+                        })
                     }
-                    ChatCompletionRequestUserMessageContent::Array(_) => {
-                        Err(Error::UnexpectedOpenAiResponse {
-                            reason: "Image chat not supported yet".to_string(),
+                    ChatCompletionRequestUserMessageContent::Array(parts) => {
+                        let mut texts = vec![];
+                        let mut images = vec![];
+                        for part in parts {
+                            match part {
+                                ChatCompletionRequestMessageContentPart::Text(
+                                    text_part,
+                                ) => texts.push(text_part.text),
+                                ChatCompletionRequestMessageContentPart::Image(
+                                    image_part,
+                                ) => images.push(ChatImage::from_data_url(
+                                    &image_part.image_url.url,
+                                )?),
+                            }
+                        }
+                        Ok(ChatMessage::Human {
+                            text: texts.join("\n"),
+                            images,
                         })
                     }
                 }
             }
             ChatCompletionRequestMessage::Assistant(assistant_message) => {
-                match assistant_message.content {
-                    Some(content) => Ok(ChatMessage::AI { text: content }),
-                    None => Err(Error::UnexpectedOpenAiResponse {
-                        reason: "AI function calls not supported yet".to_string(),
+                match (assistant_message.tool_calls, assistant_message.content) {
+                    (Some(tool_calls), _) if !tool_calls.is_empty() => {
+                        Ok(ChatMessage::AIToolCalls {
+                            tool_calls: tool_calls
+                                .into_iter()
+                                .map(Into::into)
+                                .collect(),
+                        })
+                    }
+                    (_, Some(content)) => Ok(ChatMessage::AI { text: content }),
+                    _ => Err(Error::UnexpectedOpenAiResponse {
+                        reason: "AI message has neither content nor tool calls"
+                            .to_string(),
                     }),
                 }
             }
+            ChatCompletionRequestMessage::Tool(tool_message) => Ok(ChatMessage::Tool {
+                tool_call_id: tool_message.tool_call_id,
+                text: tool_message.content,
+            }),
             _ => Err(Error::UnexpectedOpenAiResponse {
-                reason: "Only AI text chat is supported".to_string(),
+                reason: "Legacy function messages are not supported".to_string(),
             }),
         }
     }
@@ -119,12 +251,20 @@ impl TryFrom<ChatCompletionResponseMessage> for ChatMessage {
     type Error = Error;
 
     fn try_from(message: ChatCompletionResponseMessage) -> Result<Self, Self::Error> {
+        if let Some(tool_calls) = message.tool_calls.filter(|calls| !calls.is_empty()) {
+            return Ok(ChatMessage::AIToolCalls {
+                tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+            });
+        }
         let text = message.content.ok_or(Error::UnexpectedOpenAiResponse {
             reason: "No content in response".to_string(),
         })?;
         match message.role {
             Role::System => Ok(ChatMessage::System { text }),
-            Role::User => Ok(ChatMessage::Human { text }),
+            Role::User => Ok(ChatMessage::Human {
+                text,
+                images: vec![],
+            }),
             Role::Assistant => Ok(ChatMessage::AI { text }),
             _ => Err(Error::UnexpectedOpenAiResponse {
                 reason: "Only AI text chat is supported".to_string(),
@@ -133,9 +273,12 @@ impl TryFrom<ChatCompletionResponseMessage> for ChatMessage {
     }
 }
 
-impl From<ChatMessage> for ChatCompletionRequestMessage {
-    fn from(val: ChatMessage) -> Self {
-        match val {
+/// Images must already be loaded as base64 data for the conversion to succeed.
+impl TryFrom<ChatMessage> for ChatCompletionRequestMessage {
+    type Error = Error;
+
+    fn try_from(val: ChatMessage) -> Result<Self, Self::Error> {
+        let message = match val {
             ChatMessage::System { text } => ChatCompletionRequestMessage::System(
                 ChatCompletionRequestSystemMessage {
                     content: text,
@@ -143,9 +286,35 @@ impl From<ChatMessage> for ChatCompletionRequestMessage {
                     ..Default::default()
                 },
             ),
-            ChatMessage::Human { text } => {
+            ChatMessage::Human { text, images } => {
+                let content = if images.is_empty() {
+                    ChatCompletionRequestUserMessageContent::Text(text)
+                } else {
+                    let mut parts = vec![];
+                    if !text.is_empty() {
+                        let text_part =
+                            ChatCompletionRequestMessageContentPartTextArgs::default()
+                                .text(text)
+                                .build()?;
+                        parts.push(ChatCompletionRequestMessageContentPart::Text(
+                            text_part,
+                        ));
+                    }
+                    for image in images {
+                        let image_url =
+                            ImageUrlArgs::default().url(image.data_url()?).build()?;
+                        let image_part =
+                            ChatCompletionRequestMessageContentPartImageArgs::default()
+                                .image_url(image_url)
+                                .build()?;
+                        parts.push(ChatCompletionRequestMessageContentPart::Image(
+                            image_part,
+                        ));
+                    }
+                    ChatCompletionRequestUserMessageContent::Array(parts)
+                };
                 ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
-                    content: ChatCompletionRequestUserMessageContent::Text(text),
+                    content,
                     role: Role::User,
                     ..Default::default()
                 })
@@ -157,7 +326,27 @@ impl From<ChatMessage> for ChatCompletionRequestMessage {
                     ..Default::default()
                 },
             ),
-        }
+            ChatMessage::AIToolCalls { tool_calls } => {
+                ChatCompletionRequestMessage::Assistant(
+                    ChatCompletionRequestAssistantMessage {
+                        content: None,
+                        role: Role::Assistant,
+                        tool_calls: Some(
+                            tool_calls.into_iter().map(Into::into).collect(),
+                        ),
+                        ..Default::default()
+                    },
+                )
+            }
+            ChatMessage::Tool { tool_call_id, text } => {
+                ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                    role: Role::Tool,
+                    content: text,
+                    tool_call_id,
+                })
+            }
+        };
+        Ok(message)
     }
 }
 
@@ -227,11 +416,13 @@ impl TryFrom<Vec<ChatCompletionRequestMessage>> for ChatPrompt {
     }
 }
 
-impl From<ChatPrompt> for Vec<ChatCompletionRequestMessage> {
-    fn from(val: ChatPrompt) -> Self {
+impl TryFrom<ChatPrompt> for Vec<ChatCompletionRequestMessage> {
+    type Error = Error;
+
+    fn try_from(val: ChatPrompt) -> Result<Self, Self::Error> {
         val.messages
             .into_iter()
-            .map(|message| message.into())
+            .map(|message| message.try_into())
             .collect()
     }
 }
@@ -259,6 +450,143 @@ where
     }
 }
 
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    AsExpression,
+    FromSqlRow,
+    specta::Type,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// Constrains the model to produce valid JSON.
+    JsonObject,
+}
+
+impl ToSql<Text, Sqlite> for ResponseFormat
+where
+    String: ToSql<Text, Sqlite>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for ResponseFormat
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let format_str = String::from_sql(bytes)?;
+        Ok(ResponseFormat::from_str(&format_str)?)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    specta::Type,
+)]
+#[diesel(sql_type = Text)]
+#[serde(transparent)]
+pub struct StopSequences(pub Vec<String>);
+
+impl ToSql<Text, Sqlite> for StopSequences
+where
+    String: ToSql<Text, Sqlite>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        let json_str = serde_json::to_string(&self)?;
+        out.set_value(json_str);
+        Ok(IsNull::No)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for StopSequences
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let json_str = String::from_sql(bytes)?;
+        let parsed_json: Self = serde_json::from_str(&json_str)?;
+        Ok(parsed_json)
+    }
+}
+
+/// Names of the local tools that the model is allowed to call.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    specta::Type,
+)]
+#[diesel(sql_type = Text)]
+#[serde(transparent)]
+pub struct ToolNames(pub Vec<String>);
+
+impl ToSql<Text, Sqlite> for ToolNames
+where
+    String: ToSql<Text, Sqlite>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        let json_str = serde_json::to_string(&self)?;
+        out.set_value(json_str);
+        Ok(IsNull::No)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for ToolNames
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let json_str = String::from_sql(bytes)?;
+        let parsed_json: Self = serde_json::from_str(&json_str)?;
+        Ok(parsed_json)
+    }
+}
+
+/// Optional settings that control how a completion is generated. Unset values
+/// are left up to the provider's defaults.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct ChatParameters {
+    pub max_tokens: Option<i32>,
+    pub top_p: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub stop: Option<StopSequences>,
+    pub seed: Option<i64>,
+    pub response_format: Option<ResponseFormat>,
+    /// Number of alternative completions to generate.
+    pub n: Option<i32>,
+    /// Local tools to offer the model. The chat keeps going until the model stops
+    /// calling them and replies with text.
+    pub tools: Option<ToolNames>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, specta::Type)]
 pub struct Llm {
     pub name: String,
@@ -270,11 +598,135 @@ pub struct Llm {
 pub struct Request {
     pub prompt: Prompt,
     pub temperature: f32,
+    #[serde(flatten)]
+    pub parameters: ChatParameters,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    Display,
+    AsExpression,
+    FromSqlRow,
+    specta::Type,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum FinishReason {
+    Stop,
+    Length,
+    ToolCalls,
+    ContentFilter,
+    FunctionCall,
+    /// Cancelled by the user before the provider replied.
+    Aborted,
+}
+
+impl From<async_openai::types::FinishReason> for FinishReason {
+    fn from(reason: async_openai::types::FinishReason) -> Self {
+        match reason {
+            async_openai::types::FinishReason::Stop => FinishReason::Stop,
+            async_openai::types::FinishReason::Length => FinishReason::Length,
+            async_openai::types::FinishReason::ToolCalls => FinishReason::ToolCalls,
+            async_openai::types::FinishReason::ContentFilter => {
+                FinishReason::ContentFilter
+            }
+            async_openai::types::FinishReason::FunctionCall => {
+                FinishReason::FunctionCall
+            }
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for FinishReason
+where
+    String: ToSql<Text, Sqlite>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for FinishReason
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let reason_str = String::from_sql(bytes)?;
+        Ok(FinishReason::from_str(&reason_str)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
+pub struct CompletionChoice {
+    pub completion: ChatMessage,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    specta::Type,
+)]
+#[diesel(sql_type = Text)]
+#[serde(transparent)]
+pub struct CompletionChoices(pub Vec<CompletionChoice>);
+
+impl Deref for CompletionChoices {
+    type Target = Vec<CompletionChoice>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ToSql<Text, Sqlite> for CompletionChoices
+where
+    String: ToSql<Text, Sqlite>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        let json_str = serde_json::to_string(&self)?;
+        out.set_value(json_str);
+        Ok(IsNull::No)
+    }
+}
+
+impl<DB> FromSql<Text, DB> for CompletionChoices
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        let json_str = String::from_sql(bytes)?;
+        let parsed_json: Self = serde_json::from_str(&json_str)?;
+        Ok(parsed_json)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
 pub struct Response {
     pub completion: ChatMessage,
+    /// Why the model stopped generating the completion. A `length` finish means that
+    /// the completion was cut off.
+    pub finish_reason: Option<FinishReason>,
+    /// Every completion that was generated, when more than one was requested. The
+    /// first of these is also the `completion` above.
+    #[serde(default, skip_serializing_if = "CompletionChoices::is_empty")]
+    pub choices: CompletionChoices,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
@@ -284,7 +736,7 @@ pub struct TokenMetadata {
     pub total: Option<i32>,
 }
 
-#[derive(Debug, Queryable, Selectable, Clone)]
+#[derive(Debug, Queryable, QueryableByName, Selectable, Clone)]
 #[diesel(table_name = llm_calls)]
 pub struct LlmCallRow {
     pub id: EntityId,
@@ -298,6 +750,20 @@ pub struct LlmCallRow {
     pub total_tokens: Option<i32>,
     pub prompt: Prompt,
     pub completion: ChatMessage,
+    pub conversation_id: Option<EntityId>,
+    pub previous_call_id: Option<EntityId>,
+    pub cost: Option<f64>,
+    pub max_tokens: Option<i32>,
+    pub top_p: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub stop: Option<StopSequences>,
+    pub seed: Option<i64>,
+    pub response_format: Option<ResponseFormat>,
+    pub n: Option<i32>,
+    pub choices: Option<CompletionChoices>,
+    pub finish_reason: Option<FinishReason>,
+    pub tools: Option<ToolNames>,
 }
 
 #[derive(Insertable)]
@@ -314,6 +780,20 @@ pub struct NewLlmCallRow<'a> {
     pub total_tokens: Option<&'a i32>,
     pub prompt: &'a Prompt,
     pub completion: &'a ChatMessage,
+    pub conversation_id: Option<&'a EntityId>,
+    pub previous_call_id: Option<&'a EntityId>,
+    pub cost: Option<&'a f64>,
+    pub max_tokens: Option<&'a i32>,
+    pub top_p: Option<&'a f32>,
+    pub presence_penalty: Option<&'a f32>,
+    pub frequency_penalty: Option<&'a f32>,
+    pub stop: Option<&'a StopSequences>,
+    pub seed: Option<&'a i64>,
+    pub response_format: Option<&'a ResponseFormat>,
+    pub n: Option<&'a i32>,
+    pub choices: Option<&'a CompletionChoices>,
+    pub finish_reason: Option<&'a FinishReason>,
+    pub tools: Option<&'a ToolNames>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
@@ -325,6 +805,14 @@ pub struct LlmCall {
     pub request: Request,
     pub response: Response,
     pub tokens: TokenMetadata,
+    /// Cost of the call in US dollars, if known.
+    pub cost: Option<f64>,
+    pub conversation_id: Option<EntityId>,
+    pub previous_call_id: Option<EntityId>,
+    /// Something the user should know about the call, such as it not having been
+    /// saved to the database. This is never stored itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
 }
 
 impl LlmCall {
@@ -341,6 +829,20 @@ impl LlmCall {
             total_tokens: self.tokens.total.as_ref(),
             prompt: &self.request.prompt,
             completion: &self.response.completion,
+            conversation_id: self.conversation_id.as_ref(),
+            previous_call_id: self.previous_call_id.as_ref(),
+            cost: self.cost.as_ref(),
+            max_tokens: self.request.parameters.max_tokens.as_ref(),
+            top_p: self.request.parameters.top_p.as_ref(),
+            presence_penalty: self.request.parameters.presence_penalty.as_ref(),
+            frequency_penalty: self.request.parameters.frequency_penalty.as_ref(),
+            stop: self.request.parameters.stop.as_ref(),
+            seed: self.request.parameters.seed.as_ref(),
+            response_format: self.request.parameters.response_format.as_ref(),
+            n: self.request.parameters.n.as_ref(),
+            choices: Some(&self.response.choices).filter(|c| !c.is_empty()),
+            finish_reason: self.response.finish_reason.as_ref(),
+            tools: self.request.parameters.tools.as_ref(),
         }
     }
 }
@@ -357,9 +859,22 @@ impl From<LlmCallRow> for LlmCall {
         let request = Request {
             prompt: row.prompt,
             temperature: row.temperature,
+            parameters: ChatParameters {
+                max_tokens: row.max_tokens,
+                top_p: row.top_p,
+                presence_penalty: row.presence_penalty,
+                frequency_penalty: row.frequency_penalty,
+                stop: row.stop,
+                seed: row.seed,
+                response_format: row.response_format,
+                n: row.n,
+                tools: row.tools,
+            },
         };
         let response = Response {
             completion: row.completion,
+            finish_reason: row.finish_reason,
+            choices: row.choices.unwrap_or_default(),
         };
         let token_metadata = TokenMetadata {
             prompt: row.prompt_tokens,
@@ -373,6 +888,10 @@ impl From<LlmCallRow> for LlmCall {
             request,
             response,
             tokens: token_metadata,
+            cost: row.cost,
+            conversation_id: row.conversation_id,
+            previous_call_id: row.previous_call_id,
+            warning: None,
         }
     }
 }
@@ -1,4 +1,5 @@
 pub mod api_keys;
+pub mod conversations;
 pub mod llm_calls;
 
 pub use api_keys::{ApiKey, NewApiKey};
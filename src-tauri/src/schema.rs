@@ -7,6 +7,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    conversations (id) {
+        id -> Text,
+        title -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     llm_calls (id) {
         id -> Text,
@@ -20,7 +29,21 @@ diesel::table! {
         total_tokens -> Nullable<Integer>,
         prompt -> Text,
         completion -> Text,
+        conversation_id -> Nullable<Text>,
+        previous_call_id -> Nullable<Text>,
+        cost -> Nullable<Double>,
+        max_tokens -> Nullable<Integer>,
+        top_p -> Nullable<Float>,
+        presence_penalty -> Nullable<Float>,
+        frequency_penalty -> Nullable<Float>,
+        stop -> Nullable<Text>,
+        seed -> Nullable<BigInt>,
+        response_format -> Nullable<Text>,
+        n -> Nullable<Integer>,
+        choices -> Nullable<Text>,
+        finish_reason -> Nullable<Text>,
+        tools -> Nullable<Text>,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(api_keys, llm_calls,);
+diesel::allow_tables_to_appear_in_same_query!(api_keys, conversations, llm_calls,);
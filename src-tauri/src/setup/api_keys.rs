@@ -1,5 +1,9 @@
+use crate::commands::errors::ZammResult;
 use crate::models::ApiKey;
 use crate::schema::api_keys;
+use crate::setup::secrets::passphrase::{is_encrypted, Passphrase};
+use crate::setup::secrets::SecretStore;
+use crate::ZammDatabase;
 use diesel;
 use diesel::deserialize::FromSqlRow;
 use diesel::expression::AsExpression;
@@ -27,43 +31,277 @@ use strum_macros::{Display, EnumString};
 #[strum(serialize_all = "snake_case")]
 pub enum Service {
     OpenAI,
+    Anthropic,
+    /// Any locally hosted server that exposes an OpenAI-compatible API, such as
+    /// Ollama or the llama.cpp server. These don't require API keys.
+    Ollama,
+}
+
+impl Service {
+    /// Environment variable that the API key for this service is conventionally
+    /// stored in, if this service requires an API key at all.
+    pub fn env_var(&self) -> Option<&'static str> {
+        match self {
+            Service::OpenAI => Some("OPENAI_API_KEY"),
+            Service::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Service::Ollama => None,
+        }
+    }
+}
+
+/// Where the API key currently in use came from.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum ApiKeySource {
+    /// Set by the environment variable, which overrides any saved key.
+    Environment { env_var: String },
+    /// Saved to the OS keyring or to the encrypted secrets file.
+    SecretStore,
+    /// Saved to the database, either protected by a passphrase or left there in
+    /// plaintext by an older version of ZAMM.
+    Database,
+    /// Set from ZAMM and also exported from this shell init file.
+    InitFile { path: String },
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, Type)]
 pub struct ApiKeys {
     pub openai: Option<String>,
+    pub anthropic: Option<String>,
+    pub openai_source: Option<ApiKeySource>,
+    pub anthropic_source: Option<ApiKeySource>,
+    /// Whether there are passphrase-protected keys in the database that have yet to
+    /// be unlocked.
+    pub locked: bool,
+    /// Whether plaintext keys were just moved or deleted from the database, which
+    /// means that backups made before then still have them.
+    pub plaintext_in_backups: bool,
+    /// The passphrase that keys get protected with, once the user has given one.
+    #[serde(skip)]
+    pub passphrase: Option<Passphrase>,
+}
+
+/// Just enough of an API key to tell which one it is, without giving it away.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Type)]
+pub struct MaskedApiKey {
+    pub prefix: String,
+    pub last_four: String,
+    pub source: Option<ApiKeySource>,
+}
+
+impl MaskedApiKey {
+    const PREFIX_LENGTH: usize = 3;
+    const SUFFIX_LENGTH: usize = 4;
+
+    pub fn new(api_key: &str, source: Option<ApiKeySource>) -> Self {
+        let chars: Vec<char> = api_key.chars().collect();
+        // short keys would be mostly given away by their ends alone
+        if chars.len() < 2 * (Self::PREFIX_LENGTH + Self::SUFFIX_LENGTH) {
+            return MaskedApiKey {
+                prefix: String::new(),
+                last_four: String::new(),
+                source,
+            };
+        }
+        MaskedApiKey {
+            prefix: chars[..Self::PREFIX_LENGTH].iter().collect(),
+            last_four: chars[chars.len() - Self::SUFFIX_LENGTH..].iter().collect(),
+            source,
+        }
+    }
+}
+
+/// The API keys as shown to the frontend, which only gets the full key when it
+/// explicitly asks for it.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize, Type)]
+pub struct MaskedApiKeys {
+    pub openai: Option<MaskedApiKey>,
+    pub anthropic: Option<MaskedApiKey>,
+    pub locked: bool,
+    pub plaintext_in_backups: bool,
 }
 
 impl ApiKeys {
-    pub fn update(&mut self, service: &Service, key: String) {
+    pub fn get(&self, service: &Service) -> Option<&String> {
         match service {
-            Service::OpenAI => self.openai = Some(key),
+            Service::OpenAI => self.openai.as_ref(),
+            Service::Anthropic => self.anthropic.as_ref(),
+            Service::Ollama => None,
+        }
+    }
+
+    pub fn source(&self, service: &Service) -> Option<&ApiKeySource> {
+        match service {
+            Service::OpenAI => self.openai_source.as_ref(),
+            Service::Anthropic => self.anthropic_source.as_ref(),
+            Service::Ollama => None,
+        }
+    }
+
+    pub fn masked(&self) -> MaskedApiKeys {
+        let mask = |service: &Service| {
+            self.get(service).map(|api_key| {
+                MaskedApiKey::new(api_key, self.source(service).cloned())
+            })
+        };
+        MaskedApiKeys {
+            openai: mask(&Service::OpenAI),
+            anthropic: mask(&Service::Anthropic),
+            locked: self.locked,
+            plaintext_in_backups: self.plaintext_in_backups,
+        }
+    }
+
+    pub fn update(&mut self, service: &Service, key: String, source: ApiKeySource) {
+        match service {
+            Service::OpenAI => {
+                self.openai = Some(key);
+                self.openai_source = Some(source);
+            }
+            Service::Anthropic => {
+                self.anthropic = Some(key);
+                self.anthropic_source = Some(source);
+            }
+            Service::Ollama => (),
         }
     }
 
     pub fn remove(&mut self, service: &Service) {
         match service {
-            Service::OpenAI => self.openai = None,
+            Service::OpenAI => {
+                self.openai = None;
+                self.openai_source = None;
+            }
+            Service::Anthropic => {
+                self.anthropic = None;
+                self.anthropic_source = None;
+            }
+            Service::Ollama => (),
+        }
+    }
+}
+
+/// The API key for the service from its environment variable, which takes
+/// precedence over any saved key.
+pub fn env_api_key(service: &Service) -> Option<String> {
+    service.env_var().and_then(|env_var| env::var(env_var).ok())
+}
+
+pub fn load_db_api_keys(zamm_db: &ZammDatabase) -> ZammResult<Vec<ApiKey>> {
+    match zamm_db.try_connection()? {
+        Some(mut conn) => Ok(api_keys::table.load(&mut *conn)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Deletes a plaintext key from the database for good.
+fn delete_db_api_key(zamm_db: &ZammDatabase, service: &Service) -> ZammResult<()> {
+    let conn = &mut *zamm_db.connection()?;
+    // the deleted key gets overwritten instead of being left in a free page, and
+    // the write-ahead log that still has it gets emptied out
+    conn.batch_execute("PRAGMA secure_delete = ON;")?;
+    diesel::delete(api_keys::table)
+        .filter(api_keys::service.eq(service))
+        .execute(conn)?;
+    conn.batch_execute("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    Ok(())
+}
+
+/// Moves a key that was saved in plaintext by an older version of ZAMM out of the
+/// database and into the secret store, and returns the key that ends up in use.
+fn migrate_db_api_key(
+    zamm_db: &ZammDatabase,
+    secrets: &dyn SecretStore,
+    api_key: &ApiKey,
+) -> ZammResult<String> {
+    // the secret store is shared by every database, so a key moved out of another
+    // one must not get replaced. The one in the database gets dropped instead, so
+    // that it doesn't stay around in plaintext
+    if let Some(stored) = secrets.get(&api_key.service)? {
+        if stored != api_key.api_key {
+            delete_db_api_key(zamm_db, &api_key.service)?;
+            eprintln!(
+                "Deleted {} API key from the database instead of moving it, because \
+                {} already has a different one that is used instead. Backups made \
+                before now still have the deleted key in plaintext.",
+                api_key.service,
+                secrets.name()
+            );
+            return Ok(stored);
         }
     }
+
+    secrets.set(&api_key.service, &api_key.api_key)?;
+    delete_db_api_key(zamm_db, &api_key.service)?;
+    println!(
+        "Moved {} API key out of the database and into {}. Backups made before now \
+        still have it in plaintext.",
+        api_key.service,
+        secrets.name()
+    );
+    Ok(api_key.api_key.clone())
 }
 
-pub fn setup_api_keys(possible_db: &mut Option<SqliteConnection>) -> ApiKeys {
-    let mut api_keys = ApiKeys { openai: None };
+pub fn setup_api_keys(zamm_db: &ZammDatabase, secrets: &dyn SecretStore) -> ApiKeys {
+    let mut api_keys = ApiKeys::default();
+
+    for service in [Service::OpenAI, Service::Anthropic] {
+        match secrets.get(&service) {
+            Ok(Some(api_key)) => {
+                api_keys.update(&service, api_key, ApiKeySource::SecretStore)
+            }
+            Ok(None) => (),
+            Err(e) => eprintln!(
+                "Failed to load {service} API key from {}: {e}",
+                secrets.name()
+            ),
+        }
+    }
 
-    if let Some(conn) = possible_db.as_mut() {
-        let load_result: Result<Vec<ApiKey>, diesel::result::Error> =
-            api_keys::table.load(conn);
-        if let Ok(api_keys_rows) = load_result {
+    // keys that can't be loaded are left for the user to set again, but the reason
+    // why shows up in the database status
+    match load_db_api_keys(zamm_db) {
+        Ok(api_keys_rows) => {
             for api_key in api_keys_rows {
-                api_keys.update(&api_key.service, api_key.api_key);
+                // protected keys stay where they are until they get unlocked
+                if is_encrypted(&api_key.api_key) {
+                    api_keys.locked = true;
+                    continue;
+                }
+                // keys that can't be moved yet still get used for now
+                match migrate_db_api_key(zamm_db, secrets, &api_key) {
+                    Ok(key) => {
+                        api_keys.plaintext_in_backups = true;
+                        api_keys.update(
+                            &api_key.service,
+                            key,
+                            ApiKeySource::SecretStore,
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to move {} API key: {e}", api_key.service);
+                        api_keys.update(
+                            &api_key.service,
+                            api_key.api_key,
+                            ApiKeySource::Database,
+                        );
+                    }
+                }
             }
         }
+        Err(e) => zamm_db.record_error(&e),
     }
 
-    // database keys will get overridden by environment keys
-    if let Ok(openai_api_key) = env::var("OPENAI_API_KEY") {
-        api_keys.openai = Some(openai_api_key);
+    // stored keys will get overridden by environment keys
+    for service in [Service::OpenAI, Service::Anthropic] {
+        if let (Some(env_var), Some(env_api_key)) =
+            (service.env_var(), env_api_key(&service))
+        {
+            let source = ApiKeySource::Environment {
+                env_var: env_var.to_string(),
+            };
+            api_keys.update(&service, env_api_key, source);
+        }
     }
 
     api_keys
@@ -73,22 +311,43 @@ pub fn setup_api_keys(possible_db: &mut Option<SqliteConnection>) -> ApiKeys {
 mod tests {
     use super::*;
     use crate::models::NewApiKey;
-    use crate::setup::db::MIGRATIONS;
-    use diesel_migrations::MigrationHarness;
+    use crate::setup::secrets::MemoryStore;
+    use crate::test_helpers::setup_zamm_db;
     use temp_env;
 
     const DUMMY_API_KEY: &str = "0p3n41-4p1-k3y";
 
-    fn setup_database() -> SqliteConnection {
-        let mut conn = SqliteConnection::establish(":memory:").unwrap();
-        conn.run_pending_migrations(MIGRATIONS).unwrap();
-        conn
+    fn insert_api_key(db: &ZammDatabase, service: Service, api_key: &str) {
+        diesel::insert_into(api_keys::table)
+            .values(&NewApiKey { service, api_key })
+            .execute(&mut *db.connection().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_masked_api_keys() {
+        let api_keys = ApiKeys {
+            openai: Some("sk-pr0j3ct-0p3n41-4p1-k3y".to_string()),
+            anthropic: Some("sh0rt-k3y".to_string()),
+            ..ApiKeys::default()
+        };
+        let masked = api_keys.masked();
+        assert_eq!(
+            masked.openai,
+            Some(MaskedApiKey {
+                prefix: "sk-".to_string(),
+                last_four: "-k3y".to_string(),
+                source: None,
+            })
+        );
+        assert_eq!(masked.anthropic.unwrap().last_four, "");
     }
 
     #[test]
     fn test_get_empty_api_keys_no_db() {
         temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
-            let api_keys = setup_api_keys(&mut None);
+            let api_keys =
+                setup_api_keys(&ZammDatabase::new(None, None), &MemoryStore::default());
             assert!(api_keys.openai.is_none());
         });
     }
@@ -96,7 +355,8 @@ mod tests {
     #[test]
     fn test_get_present_api_keys_no_db() {
         temp_env::with_var("OPENAI_API_KEY", Some(DUMMY_API_KEY), || {
-            let api_keys = setup_api_keys(&mut None);
+            let api_keys =
+                setup_api_keys(&ZammDatabase::new(None, None), &MemoryStore::default());
             assert_eq!(api_keys.openai, Some(DUMMY_API_KEY.to_string()));
         });
     }
@@ -104,16 +364,10 @@ mod tests {
     #[test]
     fn test_get_api_keys_from_db() {
         temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
-            let mut conn = setup_database();
-            diesel::insert_into(api_keys::table)
-                .values(&NewApiKey {
-                    service: Service::OpenAI,
-                    api_key: DUMMY_API_KEY,
-                })
-                .execute(&mut conn)
-                .unwrap();
+            let db = setup_zamm_db();
+            insert_api_key(&db, Service::OpenAI, DUMMY_API_KEY);
 
-            let api_keys = setup_api_keys(&mut Some(conn));
+            let api_keys = setup_api_keys(&db, &MemoryStore::default());
             assert_eq!(api_keys.openai, Some(DUMMY_API_KEY.to_string()));
         });
     }
@@ -123,27 +377,115 @@ mod tests {
         let custom_api_key = "c0st0m-4p1-k3y";
 
         temp_env::with_var("OPENAI_API_KEY", Some(custom_api_key.to_string()), || {
-            let mut conn = setup_database();
-            diesel::insert_into(api_keys::table)
-                .values(&NewApiKey {
-                    service: Service::OpenAI,
-                    api_key: DUMMY_API_KEY,
-                })
-                .execute(&mut conn)
-                .unwrap();
+            let db = setup_zamm_db();
+            insert_api_key(&db, Service::OpenAI, DUMMY_API_KEY);
 
-            let api_keys = setup_api_keys(&mut Some(conn));
+            let api_keys = setup_api_keys(&db, &MemoryStore::default());
             assert_eq!(api_keys.openai, Some(custom_api_key.to_string()));
+            assert_eq!(
+                api_keys.openai_source,
+                Some(ApiKeySource::Environment {
+                    env_var: "OPENAI_API_KEY".to_string()
+                })
+            );
         });
     }
 
+    #[test]
+    fn test_get_anthropic_key_from_env() {
+        temp_env::with_vars(
+            [
+                ("OPENAI_API_KEY", None::<&str>),
+                ("ANTHROPIC_API_KEY", Some(DUMMY_API_KEY)),
+            ],
+            || {
+                let api_keys = setup_api_keys(
+                    &ZammDatabase::new(None, None),
+                    &MemoryStore::default(),
+                );
+                assert_eq!(api_keys.openai, None);
+                assert_eq!(api_keys.anthropic, Some(DUMMY_API_KEY.to_string()));
+            },
+        );
+    }
+
     #[test]
     fn test_empty_db_doesnt_crash() {
         temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
-            let conn = setup_database();
+            let db = setup_zamm_db();
 
-            let api_keys = setup_api_keys(&mut Some(conn));
+            let api_keys = setup_api_keys(&db, &MemoryStore::default());
             assert_eq!(api_keys.openai, None);
+            assert_eq!(db.last_error().unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_load_error_recorded() {
+        temp_env::with_var("OPENAI_API_KEY", Some(DUMMY_API_KEY), || {
+            let db = setup_zamm_db();
+            diesel::sql_query("DROP TABLE api_keys")
+                .execute(&mut *db.connection().unwrap())
+                .unwrap();
+
+            // environment keys still get picked up
+            let api_keys = setup_api_keys(&db, &MemoryStore::default());
+            assert_eq!(api_keys.openai, Some(DUMMY_API_KEY.to_string()));
+            let last_error = db.last_error().unwrap().unwrap();
+            assert!(last_error.contains("api_keys"), "Unexpected: {last_error}");
+        });
+    }
+
+    #[test]
+    fn test_db_keys_moved_to_secret_store() {
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let db = setup_zamm_db();
+            insert_api_key(&db, Service::OpenAI, DUMMY_API_KEY);
+            let secrets = MemoryStore::default();
+
+            let api_keys = setup_api_keys(&db, &secrets);
+            assert_eq!(api_keys.openai, Some(DUMMY_API_KEY.to_string()));
+            assert_eq!(api_keys.openai_source, Some(ApiKeySource::SecretStore));
+            assert!(api_keys.plaintext_in_backups);
+            assert_eq!(
+                secrets.get(&Service::OpenAI).unwrap(),
+                Some(DUMMY_API_KEY.to_string())
+            );
+            let remaining: i64 = api_keys::table
+                .count()
+                .get_result(&mut *db.connection().unwrap())
+                .unwrap();
+            assert_eq!(remaining, 0);
+
+            // and the key is still there the next time the app starts
+            let api_keys = setup_api_keys(&db, &secrets);
+            assert_eq!(api_keys.openai, Some(DUMMY_API_KEY.to_string()));
+        });
+    }
+
+    #[test]
+    fn test_db_key_deleted_instead_of_replacing_other_databases_key() {
+        let other_api_key = "0th3r-d4t4b4s3-k3y";
+
+        temp_env::with_var("OPENAI_API_KEY", None::<String>, || {
+            let db = setup_zamm_db();
+            insert_api_key(&db, Service::OpenAI, DUMMY_API_KEY);
+            let secrets = MemoryStore::default();
+            secrets.set(&Service::OpenAI, other_api_key).unwrap();
+
+            let api_keys = setup_api_keys(&db, &secrets);
+            assert_eq!(api_keys.openai, Some(other_api_key.to_string()));
+            assert_eq!(api_keys.openai_source, Some(ApiKeySource::SecretStore));
+            assert!(api_keys.plaintext_in_backups);
+            assert_eq!(
+                secrets.get(&Service::OpenAI).unwrap(),
+                Some(other_api_key.to_string())
+            );
+            let remaining: i64 = api_keys::table
+                .count()
+                .get_result(&mut *db.connection().unwrap())
+                .unwrap();
+            assert_eq!(remaining, 0);
         });
     }
 }
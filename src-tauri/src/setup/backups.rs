@@ -0,0 +1,372 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use crate::setup::db::{check_migrations_known, DbPool};
+use crate::ZammDatabase;
+use anyhow::anyhow;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BACKUPS_DIR_NAME: &str = "backups";
+/// Backups that the user asked for are named after this, while the ones made
+/// automatically are named after what they were made before.
+const MANUAL_BACKUP: &str = "backup";
+const PRE_MIGRATION_BACKUP: &str = "pre-migration";
+const PRE_RESTORE_BACKUP: &str = "pre-restore";
+/// How many of each kind of automatic backup to keep around.
+const MAX_AUTOMATIC_BACKUPS: usize = 5;
+/// How long to wait for commands that are still using the database to finish with
+/// it before it gets swapped out.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+const CLOSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(QueryableByName)]
+struct QuickCheck {
+    #[diesel(sql_type = Text)]
+    quick_check: String,
+}
+
+#[derive(QueryableByName)]
+struct MigrationRow {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+/// Backups are kept in their own directory next to the database file.
+pub fn backups_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(BACKUPS_DIR_NAME)
+}
+
+/// Timestamped backup path such as `backups/zamm-backup-20240401-093000-123.sqlite3`,
+/// which sorts in the order the backups were made.
+fn timestamped_backup_path(db_path: &Path, kind: &str) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f");
+    backups_dir(db_path).join(format!("zamm-{kind}-{timestamp}.sqlite3"))
+}
+
+/// Writes a consistent copy of the database to a new file. Other connections can
+/// carry on using the database in the meantime.
+pub fn backup_to(conn: &mut SqliteConnection, backup_path: &Path) -> ZammResult<()> {
+    if let Some(backup_dir) = backup_path.parent() {
+        fs::create_dir_all(backup_dir)?;
+    }
+    let backup_path_str = backup_path
+        .to_str()
+        .ok_or(anyhow!("Cannot convert backup path to str"))?;
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(backup_path_str)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Deletes all but the most recent automatic backups of the given kind.
+fn rotate_backups(backup_dir: &Path, kind: &str) -> ZammResult<()> {
+    let prefix = format!("zamm-{kind}-");
+    let mut backups: Vec<PathBuf> = fs::read_dir(backup_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with(&prefix) && name.ends_with(".sqlite3"))
+                .unwrap_or(false)
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(MAX_AUTOMATIC_BACKUPS);
+    for old_backup in &backups[..excess] {
+        fs::remove_file(old_backup)?;
+    }
+    Ok(())
+}
+
+fn automatic_backup(
+    conn: &mut SqliteConnection,
+    db_path: &Path,
+    kind: &str,
+) -> ZammResult<PathBuf> {
+    let backup_path = timestamped_backup_path(db_path, kind);
+    backup_to(conn, &backup_path)?;
+    println!("Backed up DB to {}", backup_path.display());
+    rotate_backups(&backups_dir(db_path), kind)?;
+    Ok(backup_path)
+}
+
+/// Backs up a database that is about to be migrated, so that a failed upgrade
+/// can't take the user's history with it.
+pub fn backup_before_migrating(
+    conn: &mut SqliteConnection,
+    db_path: &Path,
+) -> ZammResult<()> {
+    automatic_backup(conn, db_path, PRE_MIGRATION_BACKUP)?;
+    Ok(())
+}
+
+/// Checks that a file is an intact ZAMM database that this version of the app can
+/// migrate, without modifying it.
+pub fn validate_backup(backup_path: &Path) -> ZammResult<()> {
+    let invalid = |reason: String| Error::InvalidBackup { reason };
+    // SQLite would otherwise create an empty database there
+    if !backup_path.is_file() {
+        return Err(invalid(format!("{} does not exist", backup_path.display())));
+    }
+    let backup_path_str = backup_path
+        .to_str()
+        .ok_or(anyhow!("Cannot convert backup path to str"))?;
+    let conn = &mut SqliteConnection::establish(backup_path_str)
+        .map_err(|e| invalid(e.to_string()))?;
+
+    let check: QuickCheck = diesel::sql_query("PRAGMA quick_check")
+        .get_result(conn)
+        .map_err(|e| invalid(e.to_string()))?;
+    if check.quick_check != "ok" {
+        return Err(invalid(check.quick_check));
+    }
+    // reading the migrations through the migration harness would create the table
+    // for them if it's missing
+    let applied: Vec<String> =
+        diesel::sql_query("SELECT version FROM __diesel_schema_migrations")
+            .load::<MigrationRow>(conn)
+            .map_err(|e| invalid(e.to_string()))?
+            .into_iter()
+            .map(|row| row.version)
+            .collect();
+    if applied.is_empty() {
+        return Err(invalid("no migrations have been run on it".to_string()));
+    }
+    check_migrations_known(&applied)
+}
+
+/// Waits for every connection checked out of a pool that has already been taken
+/// out of use to be returned, then folds the write-ahead log back into the database
+/// file and closes all of the pool's connections. Nothing is left open on the old
+/// file afterwards, nor anything that only lives in its sidecar files.
+fn checkpoint_and_close(pool: Option<DbPool>) -> ZammResult<()> {
+    let Some(pool) = pool else {
+        return Ok(());
+    };
+    let started = Instant::now();
+    while pool.state().idle_connections < pool.state().connections {
+        if started.elapsed() > CLOSE_TIMEOUT {
+            return Err(anyhow!(
+                "Database connections were still in use after {CLOSE_TIMEOUT:?}"
+            )
+            .into());
+        }
+        thread::sleep(CLOSE_POLL_INTERVAL);
+    }
+    let mut conn = pool.get()?;
+    conn.batch_execute("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    // every connection is idle, so dropping the last handle to the pool closes them
+    drop(conn);
+    drop(pool);
+    Ok(())
+}
+
+fn replace_db_file(new_path: &Path, db_path: &Path) -> ZammResult<()> {
+    fs::rename(new_path, db_path)?;
+    // the old database's write-ahead log must not get applied to the new one. It
+    // only gets removed once the swap has gone through, because the old database
+    // still needs it otherwise
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = db_path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let sidecar = PathBuf::from(sidecar);
+        if sidecar.exists() {
+            fs::remove_file(sidecar)?;
+        }
+    }
+    Ok(())
+}
+
+impl ZammDatabase {
+    /// Backs up the database to the given file, or else to a timestamped file in
+    /// the backups directory, and returns where the backup went.
+    pub fn backup(&self, backup_path: Option<PathBuf>) -> ZammResult<PathBuf> {
+        let db_path = self.path()?.ok_or(Error::MissingDatabase {})?;
+        let backup_path = backup_path
+            .unwrap_or_else(|| timestamped_backup_path(&db_path, MANUAL_BACKUP));
+        backup_to(&mut self.connection()?, &backup_path)?;
+        println!("Backed up DB to {}", backup_path.display());
+        Ok(backup_path)
+    }
+
+    /// Replaces the contents of the database with those of a backup, after making
+    /// sure that the backup is usable. The database as it was before gets backed
+    /// up first, in case the wrong backup was picked.
+    pub fn restore_from(&self, backup_path: &Path) -> ZammResult<()> {
+        validate_backup(backup_path)?;
+        let db_path = self.path()?.ok_or(Error::MissingDatabase {})?;
+        if let Some(mut conn) = self.try_connection()? {
+            automatic_backup(&mut conn, &db_path, PRE_RESTORE_BACKUP)?;
+        }
+
+        // copy the backup next to the database first, so that the swap itself is
+        // only a rename
+        let staging_path = db_path.with_extension("restoring");
+        if staging_path.exists() {
+            fs::remove_file(&staging_path)?;
+        }
+        let backup_path_str = backup_path
+            .to_str()
+            .ok_or(anyhow!("Cannot convert backup path to str"))?;
+        let mut backup_conn =
+            SqliteConnection::establish(backup_path_str).map_err(|e| anyhow!(e))?;
+        backup_to(&mut backup_conn, &staging_path)?;
+        drop(backup_conn);
+
+        // every connection to the old file has to be closed before it gets swapped
+        // out. Taking the pool away stops any more from being checked out, while
+        // the ones that already were get waited on
+        let old_pool = self.0.write()?.pool.take();
+        let replaced = checkpoint_and_close(old_pool)
+            .and_then(|_| replace_db_file(&staging_path, &db_path));
+        // this reopens the old database if the swap failed, and migrates older
+        // backups otherwise
+        let reconnected = self.reconnect();
+        // a failed swap explains a failed reconnect, but not the other way around
+        replaced?;
+        reconnected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::db::{create_pool, MIGRATIONS};
+    use crate::test_helpers::get_temp_test_dir;
+    use diesel_migrations::MigrationHarness;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn count_backups(db_path: &Path, kind: &str) -> usize {
+        let prefix = format!("zamm-{kind}-");
+        fs::read_dir(backups_dir(db_path))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| {
+                        entry.file_name().to_string_lossy().starts_with(&prefix)
+                    })
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    /// Undoes the latest migration, as if the database was last opened by an older
+    /// version of the app.
+    fn downgrade(db_path: &Path) {
+        let mut conn = SqliteConnection::establish(db_path.to_str().unwrap()).unwrap();
+        conn.revert_last_migration(MIGRATIONS).unwrap();
+    }
+
+    #[test]
+    fn test_new_database_not_backed_up() {
+        let db_path =
+            get_temp_test_dir("new_database_not_backed_up").join("zamm.sqlite3");
+        create_pool(&db_path, 1).unwrap();
+        assert_eq!(count_backups(&db_path, PRE_MIGRATION_BACKUP), 0);
+
+        // nor is an up-to-date one
+        create_pool(&db_path, 1).unwrap();
+        assert_eq!(count_backups(&db_path, PRE_MIGRATION_BACKUP), 0);
+    }
+
+    #[test]
+    fn test_backups_before_migrating_rotated() {
+        let db_path =
+            get_temp_test_dir("backups_before_migrating").join("zamm.sqlite3");
+        create_pool(&db_path, 1).unwrap();
+
+        for expected_backups in [1, 2, 3, 4, 5, 5, 5] {
+            downgrade(&db_path);
+            create_pool(&db_path, 1).unwrap();
+            assert_eq!(
+                count_backups(&db_path, PRE_MIGRATION_BACKUP),
+                expected_backups
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_backup() {
+        let test_dir = get_temp_test_dir("validate_backup");
+        assert!(matches!(
+            validate_backup(&test_dir.join("missing.sqlite3")),
+            Err(Error::InvalidBackup { .. })
+        ));
+
+        let not_a_db = test_dir.join("notes.txt");
+        fs::write(&not_a_db, "definitely not SQLite").unwrap();
+        assert!(matches!(
+            validate_backup(&not_a_db),
+            Err(Error::InvalidBackup { .. })
+        ));
+
+        let unrelated_db = test_dir.join("unrelated.sqlite3");
+        let conn =
+            &mut SqliteConnection::establish(unrelated_db.to_str().unwrap()).unwrap();
+        diesel::sql_query("CREATE TABLE notes (text TEXT)")
+            .execute(conn)
+            .unwrap();
+        assert!(matches!(
+            validate_backup(&unrelated_db),
+            Err(Error::InvalidBackup { .. })
+        ));
+
+        let zamm_db = test_dir.join("zamm.sqlite3");
+        create_pool(&zamm_db, 1).unwrap();
+        validate_backup(&zamm_db).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_waits_for_checked_out_connections() {
+        let db_path =
+            get_temp_test_dir("checkpoint_waits_for_connections").join("zamm.sqlite3");
+        let pool = create_pool(&db_path, 2).unwrap();
+        let conn = pool.get().unwrap();
+        let released = Arc::new(AtomicBool::new(false));
+        let holder = {
+            let released = released.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                released.store(true, Ordering::SeqCst);
+                drop(conn);
+            })
+        };
+
+        checkpoint_and_close(Some(pool)).unwrap();
+        assert!(released.load(Ordering::SeqCst));
+        // nothing is left in the write-ahead log
+        let wal_path = db_path.with_extension("sqlite3-wal");
+        assert!(fs::metadata(wal_path).map_or(true, |wal| wal.len() == 0));
+        holder.join().unwrap();
+    }
+
+    #[test]
+    fn test_failed_swap_keeps_write_ahead_log() {
+        let test_dir = get_temp_test_dir("failed_swap_keeps_wal");
+        let db_path = test_dir.join("zamm.sqlite3");
+        let wal_path = test_dir.join("zamm.sqlite3-wal");
+        fs::write(&db_path, "old database").unwrap();
+        fs::write(&wal_path, "old write-ahead log").unwrap();
+
+        let result = replace_db_file(&test_dir.join("missing.restoring"), &db_path);
+        assert!(result.is_err());
+        assert_eq!(
+            fs::read_to_string(&wal_path).unwrap(),
+            "old write-ahead log"
+        );
+
+        let staging_path = test_dir.join("zamm.restoring");
+        fs::write(&staging_path, "new database").unwrap();
+        replace_db_file(&staging_path, &db_path).unwrap();
+        assert_eq!(fs::read_to_string(&db_path).unwrap(), "new database");
+        assert!(!wal_path.exists());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BLOBS_DIR_NAME: &str = "blobs";
+
+/// Stores binary data (such as chat images) on disk under the SHA-256 hash of its
+/// contents, so that the database only needs to keep track of the hash.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf) -> Self {
+        BlobStore { dir }
+    }
+
+    /// Blob store that lives alongside the database file in the given directory.
+    pub fn next_to(db_dir: &Path) -> Self {
+        BlobStore::new(db_dir.join(BLOBS_DIR_NAME))
+    }
+
+    fn blob_path(&self, hash: &str) -> ZammResult<PathBuf> {
+        let is_valid_hash =
+            hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_valid_hash {
+            return Err(Error::InvalidImage {
+                reason: format!("{hash} is not a valid blob hash"),
+            });
+        }
+        Ok(self.dir.join(hash))
+    }
+
+    /// Saves the data if it isn't stored already, and returns its hash.
+    pub fn put(&self, data: &[u8]) -> ZammResult<String> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        let path = self.blob_path(&hash)?;
+        if !path.exists() {
+            fs::create_dir_all(&self.dir)?;
+            // write to a temporary file first so that a blob is never seen half
+            // written under its final name
+            let temp_path = self
+                .dir
+                .join(format!("{hash}.{}.tmp", uuid::Uuid::new_v4()));
+            fs::write(&temp_path, data)?;
+            fs::rename(&temp_path, &path)?;
+        }
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> ZammResult<Vec<u8>> {
+        let path = self.blob_path(hash)?;
+        fs::read(&path).map_err(|e| Error::InvalidImage {
+            reason: format!("Cannot read blob {hash}: {e}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::get_temp_test_dir;
+
+    #[test]
+    fn test_put_is_content_addressed() {
+        let blob_store = BlobStore::new(get_temp_test_dir("blob_store_put"));
+
+        let hash = blob_store.put(b"hello").unwrap();
+        assert_eq!(
+            hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(blob_store.put(b"hello").unwrap(), hash);
+        assert_eq!(blob_store.get(&hash).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_get_rejects_paths() {
+        let blob_store = BlobStore::new(get_temp_test_dir("blob_store_get"));
+        assert!(blob_store.get("../zamm.sqlite3").is_err());
+    }
+}
@@ -1,32 +1,231 @@
-use diesel::sqlite::SqliteConnection;
+use crate::commands::errors::ZammResult;
+use crate::commands::preferences::Preferences;
+use crate::commands::Error;
+use crate::setup::backups::backup_before_migrating;
+use crate::setup::blobs::BlobStore;
+use crate::ZammDatabase;
+use anyhow::anyhow;
+use diesel::connection::SimpleConnection;
+use diesel::migration::MigrationSource;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+use diesel::sqlite::{Sqlite, SqliteConnection};
 use directories::ProjectDirs;
+use path_absolutize::Absolutize;
 
 use diesel::Connection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::collections::HashSet;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 const DB_NAME: &str = "zamm.sqlite3";
+/// Environment variable for using a database file other than the default one.
+pub const DB_PATH_ENV_VAR: &str = "ZAMM_DB_PATH";
+const POOL_SIZE: u32 = 8;
+/// How long a connection waits for another one to finish writing before giving up.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+/// Connections get checked out from async commands, which block their worker thread
+/// while waiting for one, so a pool with none to spare gives up quickly.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(2);
 
-fn connect_to(db_path: PathBuf) -> Option<SqliteConnection> {
-    let db_path_str = db_path.to_str().expect("Cannot convert DB path to str");
-    match SqliteConnection::establish(db_path_str) {
-        Ok(conn) => {
-            println!("Connected to DB at {}", db_path_str);
-            Some(conn)
+pub type DbPool = Pool<ConnectionManager<SqliteConnection>>;
+pub type DbConnection = PooledConnection<ConnectionManager<SqliteConnection>>;
+
+/// Settings that SQLite only keeps for the lifetime of a connection, and that
+/// therefore have to be applied to every connection the pool opens.
+#[derive(Debug)]
+struct ConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(
+        &self,
+        conn: &mut SqliteConnection,
+    ) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {BUSY_TIMEOUT_MS}; PRAGMA foreign_keys = ON;"
+        ))
+        .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// The database that the app is using, and what last went wrong with it, so that
+/// problems can be shown to the user instead of only getting logged.
+#[derive(Default)]
+pub struct DatabaseState {
+    pub path: Option<PathBuf>,
+    pub pool: Option<DbPool>,
+    pub last_error: Option<String>,
+}
+
+impl ZammDatabase {
+    pub fn new(path: Option<PathBuf>, pool: Option<DbPool>) -> Self {
+        ZammDatabase(RwLock::new(DatabaseState {
+            path,
+            pool,
+            last_error: None,
+        }))
+    }
+
+    /// Opens the database at the given path. If that fails, the database is left
+    /// unavailable until it gets reconnected.
+    pub fn open(db_path: PathBuf) -> Self {
+        let zamm_db = ZammDatabase::new(Some(db_path), None);
+        // the error is kept around as the database's last error
+        let _ = zamm_db.reconnect();
+        zamm_db
+    }
+
+    /// Checks out a connection from the pool, failing if there is no database.
+    pub fn connection(&self) -> ZammResult<DbConnection> {
+        self.try_connection()?.ok_or(Error::MissingDatabase {})
+    }
+
+    /// Checks out a connection from the pool, if there is a database at all.
+    pub fn try_connection(&self) -> ZammResult<Option<DbConnection>> {
+        let pool = self.0.read()?.pool.clone();
+        match pool {
+            Some(pool) => match pool.get() {
+                Ok(conn) => Ok(Some(conn)),
+                Err(e) => {
+                    let error = Error::from(e);
+                    self.record_error(&error);
+                    Err(error)
+                }
+            },
+            None => Ok(None),
         }
-        Err(e) => {
-            eprintln!("Failed to connect to DB: {}", e);
-            None
+    }
+
+    pub fn is_available(&self) -> ZammResult<bool> {
+        Ok(self.0.read()?.pool.is_some())
+    }
+
+    pub fn path(&self) -> ZammResult<Option<PathBuf>> {
+        Ok(self.0.read()?.path.clone())
+    }
+
+    pub fn last_error(&self) -> ZammResult<Option<String>> {
+        Ok(self.0.read()?.last_error.clone())
+    }
+
+    /// Remembers a database problem so that it can be reported to the user later.
+    pub fn record_error(&self, error: &Error) {
+        eprintln!("Database error: {error}");
+        if let Ok(mut state) = self.0.write() {
+            state.last_error = Some(error.to_string());
         }
     }
+
+    /// Opens a fresh connection pool to the database file, replacing the current
+    /// one only if that succeeds.
+    pub fn reconnect(&self) -> ZammResult<()> {
+        let db_path = self.path()?.ok_or(Error::MissingDatabase {})?;
+        self.switch_to(db_path).map_err(|e| {
+            if let Ok(mut state) = self.0.write() {
+                state.last_error = Some(e.to_string());
+            }
+            e
+        })
+    }
+
+    /// Starts using the database at the given path instead, bringing its schema up
+    /// to date first. The current database stays in use if that fails.
+    pub fn switch_to(&self, db_path: PathBuf) -> ZammResult<()> {
+        match create_pool(&db_path, POOL_SIZE) {
+            Ok(pool) => {
+                println!("Connected to DB at {}", db_path.display());
+                let mut state = self.0.write()?;
+                state.path = Some(db_path);
+                state.pool = Some(pool);
+                state.last_error = None;
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Failed to connect to DB at {}: {e}", db_path.display());
+                Err(e)
+            }
+        }
+    }
+
+    /// The most recent migration applied to the database, if it is available.
+    pub fn migration_version(&self) -> ZammResult<Option<String>> {
+        let Some(mut conn) = self.try_connection()? else {
+            return Ok(None);
+        };
+        Ok(applied_migrations(&mut conn)?.into_iter().max())
+    }
 }
 
-/** Try to start SQLite database in user data dir. */
-fn get_data_dir_db() -> Option<SqliteConnection> {
+/// Versions of the migrations that have been run on the database so far.
+pub fn applied_migrations(conn: &mut SqliteConnection) -> ZammResult<Vec<String>> {
+    let applied = conn
+        .applied_migrations()
+        .map_err(|e| anyhow!("Failed to read applied migrations: {e}"))?;
+    Ok(applied.iter().map(|version| version.to_string()).collect())
+}
+
+/// Refuses databases with migrations that this version of the app doesn't know
+/// about, because they were last opened by a newer version and migrating them
+/// could lose data.
+pub fn check_migrations_known(applied: &[String]) -> ZammResult<()> {
+    let known_versions: HashSet<String> =
+        MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+            .map_err(|e| anyhow!("Failed to read embedded migrations: {e}"))?
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+    match applied
+        .iter()
+        .find(|version| !known_versions.contains(version.as_str()))
+    {
+        Some(version) => Err(Error::UnknownMigration {
+            version: version.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Opens the database at the given path, bringing its schema up to date before
+/// handing out any pooled connections to it.
+pub fn create_pool(db_path: &Path, max_size: u32) -> ZammResult<DbPool> {
+    let db_path_str = db_path
+        .to_str()
+        .ok_or(anyhow!("Cannot convert DB path to str"))?;
+    // connect directly first, so that an unusable path fails straight away instead
+    // of after the pool's connection timeout
+    let mut conn = SqliteConnection::establish(db_path_str).map_err(|e| anyhow!(e))?;
+    // WAL mode is stored in the database file itself, and lets readers carry on
+    // while another connection writes
+    conn.batch_execute("PRAGMA journal_mode = WAL;")?;
+    let applied = applied_migrations(&mut conn)?;
+    check_migrations_known(&applied)?;
+    // brand new databases have nothing worth backing up
+    let has_pending = conn
+        .has_pending_migration(MIGRATIONS)
+        .map_err(|e| anyhow!("Failed to check for pending migrations: {e}"))?;
+    if !applied.is_empty() && has_pending {
+        backup_before_migrating(&mut conn, db_path)?;
+    }
+    // each migration runs in its own transaction, so a failure leaves the schema
+    // at the last migration that succeeded
+    conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| anyhow!("Failed to run migrations: {e}"))?;
+
+    let manager = ConnectionManager::<SqliteConnection>::new(db_path_str);
+    Ok(Pool::builder()
+        .max_size(max_size)
+        .connection_timeout(CHECKOUT_TIMEOUT)
+        .connection_customizer(Box::new(ConnectionOptions))
+        .build(manager)?)
+}
+
+/** Get the user data dir, creating it if necessary. */
+pub fn get_data_dir() -> Option<PathBuf> {
     if let Some(user_dirs) = ProjectDirs::from("dev", "zamm", "ZAMM") {
         let data_dir = user_dirs.data_dir();
 
@@ -40,32 +239,183 @@ fn get_data_dir_db() -> Option<SqliteConnection> {
             }
         }
 
-        connect_to(data_dir.join(DB_NAME))
+        Some(data_dir.to_path_buf())
     } else {
         eprintln!("Cannot find user home directory.");
         None
     }
 }
 
-pub fn get_db() -> Option<SqliteConnection> {
-    let mut possible_connection = get_data_dir_db().or_else(|| {
-        eprintln!(
-            "Unable to create DB in user data dir, defaulting to current dir instead."
-        );
-        connect_to(
-            env::current_dir()
-                .expect("Failed to get current directory")
-                .join(DB_NAME),
-        )
-    });
-    if let Some(connection) = possible_connection.as_mut() {
-        match connection.run_pending_migrations(MIGRATIONS) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Failed to run migrations: {}", e);
-                return None;
-            }
+/** Resolve a user-supplied database path, which may start with `~`. */
+pub fn absolute_db_path(db_path: &str) -> PathBuf {
+    let db_path = PathBuf::from(shellexpand::tilde(db_path).as_ref());
+    let absolute_path = db_path.absolutize().map(|path| path.into_owned());
+    absolute_path.unwrap_or(db_path)
+}
+
+/** The database file that the user asked for, if any. The environment variable
+ * takes precedence over preferences, so that a single session can be pointed
+ * elsewhere without changing the saved settings. */
+fn configured_db_path(preferences: &Preferences) -> Option<PathBuf> {
+    env::var(DB_PATH_ENV_VAR)
+        .ok()
+        .filter(|db_path| !db_path.is_empty())
+        .or_else(|| preferences.database_path.clone())
+        .map(|db_path| absolute_db_path(&db_path))
+}
+
+/** Blob storage for large files such as images, kept next to the database. */
+pub fn get_blob_store(zamm_db: &ZammDatabase) -> BlobStore {
+    let db_dir = zamm_db
+        .path()
+        .ok()
+        .flatten()
+        .and_then(|db_path| db_path.parent().map(Path::to_path_buf))
+        .or_else(get_data_dir)
+        .unwrap_or_else(|| {
+            env::current_dir().expect("Failed to get current directory")
+        });
+    BlobStore::next_to(&db_dir)
+}
+
+/** Try to start SQLite database wherever the user configured it to be, or else in
+ * the user data dir, or else in the current dir. */
+pub fn get_db(preferences: &Preferences) -> ZammDatabase {
+    if let Some(db_path) = configured_db_path(preferences) {
+        // no fallbacks here, so that histories meant to be kept apart don't end up
+        // getting mixed together
+        return ZammDatabase::open(db_path);
+    }
+    if let Some(data_dir) = get_data_dir() {
+        let zamm_db = ZammDatabase::open(data_dir.join(DB_NAME));
+        if zamm_db.is_available().unwrap_or(false) {
+            return zamm_db;
         }
     }
-    possible_connection
+    eprintln!(
+        "Unable to create DB in user data dir, defaulting to current dir instead."
+    );
+    ZammDatabase::open(
+        env::current_dir()
+            .expect("Failed to get current directory")
+            .join(DB_NAME),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::conversations::Conversation;
+    use crate::models::llm_calls::EntityId;
+    use crate::schema::conversations;
+    use crate::test_helpers::setup_zamm_db_pool;
+    use diesel::prelude::*;
+    use diesel::sql_types::{Integer, Text};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(QueryableByName)]
+    struct JournalMode {
+        #[diesel(sql_type = Text)]
+        journal_mode: String,
+    }
+
+    #[derive(QueryableByName)]
+    struct ForeignKeys {
+        #[diesel(sql_type = Integer)]
+        foreign_keys: i32,
+    }
+
+    #[test]
+    fn test_connections_configured() {
+        let db = setup_zamm_db_pool(2);
+        let conn = &mut *db.connection().unwrap();
+        let journal: JournalMode = diesel::sql_query("PRAGMA journal_mode")
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(journal.journal_mode, "wal");
+        let foreign_keys: ForeignKeys = diesel::sql_query("PRAGMA foreign_keys")
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(foreign_keys.foreign_keys, 1);
+    }
+
+    #[test]
+    fn test_configured_db_path() {
+        let preferences = Preferences {
+            database_path: Some("/preferred/zamm.sqlite3".to_string()),
+            ..Preferences::default()
+        };
+        temp_env::with_var(DB_PATH_ENV_VAR, None::<&str>, || {
+            assert_eq!(configured_db_path(&Preferences::default()), None);
+            assert_eq!(
+                configured_db_path(&preferences),
+                Some(PathBuf::from("/preferred/zamm.sqlite3"))
+            );
+        });
+        temp_env::with_var(DB_PATH_ENV_VAR, Some("/env/zamm.sqlite3"), || {
+            assert_eq!(
+                configured_db_path(&preferences),
+                Some(PathBuf::from("/env/zamm.sqlite3"))
+            );
+        });
+        temp_env::with_var(DB_PATH_ENV_VAR, Some("work.sqlite3"), || {
+            assert_eq!(
+                configured_db_path(&preferences),
+                Some(env::current_dir().unwrap().join("work.sqlite3"))
+            );
+        });
+    }
+
+    #[test]
+    fn test_missing_database() {
+        let db = ZammDatabase::new(None, None);
+        assert!(matches!(db.connection(), Err(Error::MissingDatabase {})));
+        assert!(db.try_connection().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_writes_and_reads() {
+        const NUM_THREADS: usize = 4;
+        const WRITES_PER_THREAD: usize = 25;
+        let db = Arc::new(setup_zamm_db_pool(NUM_THREADS as u32));
+
+        let handles: Vec<_> = (0..NUM_THREADS)
+            .map(|thread_num| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for i in 0..WRITES_PER_THREAD {
+                        let conn = &mut *db.connection().unwrap();
+                        let now = chrono::Utc::now().naive_utc();
+                        let conversation = Conversation {
+                            id: EntityId {
+                                uuid: uuid::Uuid::new_v4(),
+                            },
+                            title: format!("Thread {thread_num} conversation {i}"),
+                            created_at: now,
+                            updated_at: now,
+                        };
+                        diesel::insert_into(conversations::table)
+                            .values(conversation.as_sql_row())
+                            .execute(conn)
+                            .unwrap();
+                        // reads shouldn't get blocked by the other threads' writes
+                        conversations::table
+                            .count()
+                            .get_result::<i64>(conn)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total: i64 = conversations::table
+            .count()
+            .get_result(&mut *db.connection().unwrap())
+            .unwrap();
+        assert_eq!(total, (NUM_THREADS * WRITES_PER_THREAD) as i64);
+    }
 }
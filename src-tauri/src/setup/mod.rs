@@ -1,5 +1,9 @@
 pub mod api_keys;
+pub mod backups;
+pub mod blobs;
 pub mod db;
+pub mod secrets;
 
 pub use api_keys::setup_api_keys;
-pub use db::get_db;
+pub use db::{get_blob_store, get_db};
+pub use secrets::get_secret_store;
@@ -0,0 +1,242 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use crate::setup::api_keys::Service;
+use crate::setup::secrets::SecretStore;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const SECRETS_FILE_NAME: &str = "secrets.json";
+const KEY_FILE_NAME: &str = "secrets.key";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedSecrets {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Keeps API keys in a file encrypted with ChaCha20-Poly1305. The key is random
+/// and kept in a separate file that only the user can read, so this mostly guards
+/// against the secrets file getting copied or synced somewhere on its own.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    /// The whole file gets rewritten on every change, so changes can't overlap.
+    lock: Mutex<()>,
+}
+
+fn failure(reason: impl Into<String>) -> Error {
+    Error::SecretStorage {
+        reason: reason.into(),
+    }
+}
+
+/// Replaces the file at the given path with one that only the current user can
+/// read.
+fn write_private(path: &Path, contents: &[u8]) -> ZammResult<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+impl EncryptedFileStore {
+    pub fn new(dir: &Path) -> Self {
+        EncryptedFileStore {
+            path: dir.join(SECRETS_FILE_NAME),
+            key_path: dir.join(KEY_FILE_NAME),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn cipher(&self) -> ZammResult<ChaCha20Poly1305> {
+        if self.key_path.exists() {
+            let encoded = fs::read_to_string(&self.key_path)?;
+            let key = BASE64
+                .decode(encoded.trim())
+                .map_err(|e| failure(format!("Invalid key file: {e}")))?;
+            if key.len() != KEY_LENGTH {
+                return Err(failure("Invalid key file: wrong key length"));
+            }
+            return Ok(ChaCha20Poly1305::new(Key::from_slice(&key)));
+        }
+        // a fresh key would never decrypt the existing secrets
+        if self.path.exists() {
+            return Err(failure(format!(
+                "{} is missing, so {} can no longer be decrypted",
+                self.key_path.display(),
+                self.path.display()
+            )));
+        }
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        write_private(&self.key_path, BASE64.encode(key).as_bytes())?;
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+
+    fn read_secrets(
+        &self,
+        cipher: &ChaCha20Poly1305,
+    ) -> ZammResult<BTreeMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file: EncryptedSecrets =
+            serde_json::from_str(&fs::read_to_string(&self.path)?)?;
+        let nonce = BASE64
+            .decode(file.nonce)
+            .map_err(|e| failure(format!("Invalid nonce: {e}")))?;
+        if nonce.len() != NONCE_LENGTH {
+            return Err(failure("Invalid nonce: wrong length"));
+        }
+        let ciphertext = BASE64
+            .decode(file.ciphertext)
+            .map_err(|e| failure(format!("Invalid ciphertext: {e}")))?;
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                failure(format!("Could not decrypt {}", self.path.display()))
+            })?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    fn write_secrets(
+        &self,
+        cipher: &ChaCha20Poly1305,
+        secrets: &BTreeMap<String, String>,
+    ) -> ZammResult<()> {
+        // nonces must never be reused with the same key
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(secrets)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| failure("Could not encrypt secrets"))?;
+        let file = EncryptedSecrets {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        write_private(&self.path, serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut BTreeMap<String, String>),
+    ) -> ZammResult<()> {
+        let _guard = self.lock.lock()?;
+        let cipher = self.cipher()?;
+        let mut secrets = self.read_secrets(&cipher)?;
+        change(&mut secrets);
+        self.write_secrets(&cipher, &secrets)
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn get(&self, service: &Service) -> ZammResult<Option<String>> {
+        let _guard = self.lock.lock()?;
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let secrets = self.read_secrets(&self.cipher()?)?;
+        Ok(secrets.get(&service.to_string()).cloned())
+    }
+
+    fn set(&self, service: &Service, secret: &str) -> ZammResult<()> {
+        self.update(|secrets| {
+            secrets.insert(service.to_string(), secret.to_string());
+        })
+    }
+
+    fn delete(&self, service: &Service) -> ZammResult<()> {
+        self.update(|secrets| {
+            secrets.remove(&service.to_string());
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::get_temp_test_dir;
+
+    const DUMMY_API_KEY: &str = "0p3n41-4p1-k3y";
+
+    #[test]
+    fn test_secrets_persisted_encrypted() {
+        let test_dir = get_temp_test_dir("secrets_persisted_encrypted");
+        let store = EncryptedFileStore::new(&test_dir);
+        assert_eq!(store.get(&Service::OpenAI).unwrap(), None);
+        store.set(&Service::OpenAI, DUMMY_API_KEY).unwrap();
+
+        let contents = fs::read_to_string(test_dir.join(SECRETS_FILE_NAME)).unwrap();
+        assert!(!contents.contains(DUMMY_API_KEY));
+
+        let reopened = EncryptedFileStore::new(&test_dir);
+        assert_eq!(
+            reopened.get(&Service::OpenAI).unwrap(),
+            Some(DUMMY_API_KEY.to_string())
+        );
+        assert_eq!(reopened.get(&Service::Anthropic).unwrap(), None);
+
+        reopened.delete(&Service::OpenAI).unwrap();
+        assert_eq!(store.get(&Service::OpenAI).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tampered_secrets_rejected() {
+        let test_dir = get_temp_test_dir("tampered_secrets_rejected");
+        let store = EncryptedFileStore::new(&test_dir);
+        store.set(&Service::OpenAI, DUMMY_API_KEY).unwrap();
+
+        let secrets_path = test_dir.join(SECRETS_FILE_NAME);
+        let mut file: EncryptedSecrets =
+            serde_json::from_str(&fs::read_to_string(&secrets_path).unwrap()).unwrap();
+        let mut ciphertext = BASE64.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = BASE64.encode(ciphertext);
+        fs::write(&secrets_path, serde_json::to_string(&file).unwrap()).unwrap();
+
+        assert!(matches!(
+            store.get(&Service::OpenAI),
+            Err(Error::SecretStorage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_key_file_not_replaced() {
+        let test_dir = get_temp_test_dir("missing_key_file_not_replaced");
+        let store = EncryptedFileStore::new(&test_dir);
+        store.set(&Service::OpenAI, DUMMY_API_KEY).unwrap();
+        fs::remove_file(test_dir.join(KEY_FILE_NAME)).unwrap();
+
+        assert!(matches!(
+            store.set(&Service::Anthropic, DUMMY_API_KEY),
+            Err(Error::SecretStorage { .. })
+        ));
+        assert!(!test_dir.join(KEY_FILE_NAME).exists());
+    }
+}
@@ -0,0 +1,32 @@
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::Service;
+use crate::setup::secrets::SecretStore;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Keeps secrets only for as long as the store exists, so that tests don't touch
+/// the real keyring.
+#[derive(Default)]
+pub struct MemoryStore(Mutex<HashMap<String, String>>);
+
+impl SecretStore for MemoryStore {
+    fn name(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn get(&self, service: &Service) -> ZammResult<Option<String>> {
+        Ok(self.0.lock()?.get(&service.to_string()).cloned())
+    }
+
+    fn set(&self, service: &Service, secret: &str) -> ZammResult<()> {
+        self.0
+            .lock()?
+            .insert(service.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, service: &Service) -> ZammResult<()> {
+        self.0.lock()?.remove(&service.to_string());
+        Ok(())
+    }
+}
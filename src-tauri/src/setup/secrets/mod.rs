@@ -0,0 +1,42 @@
+mod encrypted_file;
+#[cfg(test)]
+mod memory;
+mod os_keyring;
+pub mod passphrase;
+
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::Service;
+use crate::setup::db::get_data_dir;
+use std::env;
+
+pub use encrypted_file::EncryptedFileStore;
+#[cfg(test)]
+pub use memory::MemoryStore;
+pub use os_keyring::KeyringStore;
+
+/// Somewhere to keep API keys other than in plaintext in the database.
+pub trait SecretStore: Send + Sync {
+    /// Describes where the secrets are kept, for logging.
+    fn name(&self) -> String;
+    fn get(&self, service: &Service) -> ZammResult<Option<String>>;
+    fn set(&self, service: &Service, secret: &str) -> ZammResult<()>;
+    /// Removes the secret for the service, if there is one.
+    fn delete(&self, service: &Service) -> ZammResult<()>;
+}
+
+/// The OS keyring if there is one, or else an encrypted file in the user data dir.
+/// Headless Linux machines often have no keyring service running.
+pub fn get_secret_store() -> Box<dyn SecretStore> {
+    if KeyringStore::is_available() {
+        return Box::new(KeyringStore);
+    }
+    let secrets_dir = get_data_dir().unwrap_or_else(|| {
+        env::current_dir().expect("Failed to get current directory")
+    });
+    let store = EncryptedFileStore::new(&secrets_dir);
+    eprintln!(
+        "No OS keyring available, keeping API keys in {} instead.",
+        store.name()
+    );
+    Box::new(store)
+}
@@ -0,0 +1,48 @@
+use crate::commands::errors::ZammResult;
+use crate::setup::api_keys::Service;
+use crate::setup::secrets::SecretStore;
+use keyring::Entry;
+
+const KEYRING_SERVICE: &str = "dev.zamm.ZAMM";
+
+/// Keeps API keys in the OS keyring: the Secret Service on Linux, the Keychain on
+/// macOS and the Credential Manager on Windows.
+pub struct KeyringStore;
+
+impl KeyringStore {
+    fn entry(service: &Service) -> ZammResult<Entry> {
+        Ok(Entry::new(KEYRING_SERVICE, &service.to_string())?)
+    }
+
+    /// Whether the keyring can be reached at all.
+    pub fn is_available() -> bool {
+        let probe =
+            KeyringStore::entry(&Service::OpenAI).map(|entry| entry.get_password());
+        matches!(probe, Ok(Ok(_)) | Ok(Err(keyring::Error::NoEntry)))
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn name(&self) -> String {
+        "the OS keyring".to_string()
+    }
+
+    fn get(&self, service: &Service) -> ZammResult<Option<String>> {
+        match KeyringStore::entry(service)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, service: &Service, secret: &str) -> ZammResult<()> {
+        Ok(KeyringStore::entry(service)?.set_password(secret)?)
+    }
+
+    fn delete(&self, service: &Service) -> ZammResult<()> {
+        match KeyringStore::entry(service)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
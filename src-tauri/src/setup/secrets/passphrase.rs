@@ -0,0 +1,132 @@
+use crate::commands::errors::ZammResult;
+use crate::commands::Error;
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+
+/// Marks API keys in the database that are encrypted with a passphrase, as opposed
+/// to plaintext keys left behind by older versions of ZAMM.
+const ENCRYPTED_PREFIX: &str = "argon2id-chacha20poly1305:";
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// A passphrase that API keys get encrypted with. It never gets printed, even in
+/// debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Passphrase(String);
+
+impl Passphrase {
+    pub fn new(passphrase: String) -> Self {
+        Passphrase(passphrase)
+    }
+}
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+fn failure(reason: impl Into<String>) -> Error {
+    Error::SecretStorage {
+        reason: reason.into(),
+    }
+}
+
+/// Every value gets its own salt, so that there is nothing else to keep track of
+/// besides the encrypted value itself.
+fn cipher(passphrase: &Passphrase, salt: &[u8]) -> ZammResult<ChaCha20Poly1305> {
+    let mut key = [0u8; KEY_LENGTH];
+    Argon2::default()
+        .hash_password_into(passphrase.0.as_bytes(), salt, &mut key)
+        .map_err(|e| failure(format!("Could not derive key: {e}")))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub fn encrypt(passphrase: &Passphrase, plaintext: &str) -> ZammResult<String> {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| failure("Could not encrypt API key"))?;
+    Ok(format!(
+        "{ENCRYPTED_PREFIX}{}:{}:{}",
+        BASE64.encode(salt),
+        BASE64.encode(nonce),
+        BASE64.encode(ciphertext)
+    ))
+}
+
+/// Decrypts a value from `encrypt`. A wrong passphrase and a tampered value look
+/// the same, so both are reported as a wrong passphrase.
+pub fn decrypt(passphrase: &Passphrase, value: &str) -> ZammResult<String> {
+    let encoded = value
+        .strip_prefix(ENCRYPTED_PREFIX)
+        .ok_or_else(|| failure("API key is not encrypted"))?;
+    let parts = encoded
+        .split(':')
+        .map(|part| BASE64.decode(part))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| failure(format!("Invalid encrypted API key: {e}")))?;
+    let [salt, nonce, ciphertext] = parts.as_slice() else {
+        return Err(failure("Invalid encrypted API key: wrong number of parts"));
+    };
+    if salt.len() != SALT_LENGTH || nonce.len() != NONCE_LENGTH {
+        return Err(failure(
+            "Invalid encrypted API key: wrong salt or nonce length",
+        ));
+    }
+    let plaintext = cipher(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext.as_ref())
+        .map_err(|_| Error::IncorrectPassphrase {})?;
+    String::from_utf8(plaintext).map_err(|e| failure(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMMY_API_KEY: &str = "0p3n41-4p1-k3y";
+
+    #[test]
+    fn test_round_trip() {
+        let passphrase = Passphrase::new("correct horse battery staple".to_string());
+        let encrypted = encrypt(&passphrase, DUMMY_API_KEY).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains(DUMMY_API_KEY));
+        // the same key never encrypts to the same value twice
+        assert_ne!(encrypted, encrypt(&passphrase, DUMMY_API_KEY).unwrap());
+        assert_eq!(decrypt(&passphrase, &encrypted).unwrap(), DUMMY_API_KEY);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_rejected() {
+        let passphrase = Passphrase::new("correct horse battery staple".to_string());
+        let encrypted = encrypt(&passphrase, DUMMY_API_KEY).unwrap();
+        let wrong = Passphrase::new("incorrect horse".to_string());
+        assert!(matches!(
+            decrypt(&wrong, &encrypted),
+            Err(Error::IncorrectPassphrase {})
+        ));
+    }
+
+    #[test]
+    fn test_plaintext_not_encrypted() {
+        assert!(!is_encrypted(DUMMY_API_KEY));
+        let passphrase = Passphrase::new("correct horse battery staple".to_string());
+        assert!(matches!(
+            decrypt(&passphrase, DUMMY_API_KEY),
+            Err(Error::SecretStorage { .. })
+        ));
+    }
+}
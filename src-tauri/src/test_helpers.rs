@@ -1,6 +1,11 @@
+use crate::setup::blobs::BlobStore;
+use crate::setup::db::create_pool;
+use crate::ZammDatabase;
 use std::env;
 use std::fs;
+use std::ops::Deref;
 use std::path::PathBuf;
+use tempfile::TempDir;
 
 pub fn get_temp_test_dir(test_name: &str) -> PathBuf {
     let mut test_dir = env::temp_dir();
@@ -16,3 +21,41 @@ pub fn get_temp_test_dir(test_name: &str) -> PathBuf {
     });
     test_dir
 }
+
+/// A fresh database in its own temporary directory, which gets deleted along with
+/// the database once the test is done with it.
+pub struct TestDatabase {
+    db: ZammDatabase,
+    _dir: TempDir,
+}
+
+impl Deref for TestDatabase {
+    type Target = ZammDatabase;
+
+    fn deref(&self) -> &ZammDatabase {
+        &self.db
+    }
+}
+
+/// A fresh database in its own temporary file, so that it behaves the same way as
+/// the real one when accessed from several connections at once.
+pub fn setup_zamm_db_pool(max_size: u32) -> TestDatabase {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("zamm.sqlite3");
+    let pool = create_pool(&db_path, max_size).unwrap();
+    TestDatabase {
+        db: ZammDatabase::new(Some(db_path), Some(pool)),
+        _dir: dir,
+    }
+}
+
+/// A database with only a single connection, so that tests fail if any code tries
+/// to check out a second connection while still holding on to the first.
+pub fn setup_zamm_db() -> TestDatabase {
+    setup_zamm_db_pool(1)
+}
+
+/// Blobs are content-addressed, so tests can safely share a single store.
+pub fn setup_blob_store() -> BlobStore {
+    BlobStore::new(env::temp_dir().join("zamm/tests/blobs"))
+}